@@ -0,0 +1,136 @@
+// Alt text for post images, and checks for images which still lack it.
+
+use lol_html::{element, rewrite_str, RewriteStrSettings};
+
+use std::cell::RefCell;
+
+/// Words in camera and phone filenames which say nothing about the image.
+const FILENAME_NOISE: [&str; 6] = ["img", "image", "dsc", "dscn", "pxl", "photo"];
+
+/// Makes alt text from the filename at the end of `url`, if the filename has any descriptive
+/// words, e.g. "great-blue-heron_2.jpg" gives "great blue heron 2" but "IMG_1234.JPG" and
+/// opaque IDs give nothing.
+pub fn alt_from_filename(url: &str) -> Option<String> {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let filename = path.rsplit('/').next().unwrap_or_default();
+    let stem = filename
+        .rsplit_once('.')
+        .map_or(filename, |(stem, _extension)| stem);
+    let stem = stem.replace("%20", " ");
+    let words: Vec<&str> = stem
+        .split(|c: char| c == '_' || c == '-' || c == '+' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .collect();
+    let is_descriptive = |word: &&str| {
+        word.len() >= 3
+            && word.len() <= 20
+            && word.chars().all(|c| c.is_alphabetic())
+            && !FILENAME_NOISE.contains(&word.to_lowercase().as_str())
+    };
+    if !words.iter().any(is_descriptive) {
+        return None;
+    }
+    Some(words.join(" "))
+}
+
+/// Gives each localized image (with an `img-N` id) in `post_html` which lacks alt text the
+/// caption for it, or else text from the filename of its original URL. `captions` and
+/// `original_urls` are indexed by N - 1.
+pub fn add_alt_text(
+    post_html: &str,
+    captions: &[Option<String>],
+    original_urls: &[String],
+) -> String {
+    let element_content_handlers = vec![element!(r#"img[id^="img-"]"#, |el| {
+        if el
+            .get_attribute("alt")
+            .is_some_and(|alt| !alt.trim().is_empty())
+        {
+            return Ok(());
+        }
+        let Some(index) = el
+            .get_attribute("id")
+            .and_then(|id| id["img-".len()..].parse::<usize>().ok())
+            .and_then(|number| number.checked_sub(1))
+        else {
+            return Ok(());
+        };
+        let alt = captions.get(index).cloned().flatten().or_else(|| {
+            original_urls
+                .get(index)
+                .and_then(|url| alt_from_filename(url))
+        });
+        if let Some(alt) = alt {
+            el.set_attribute("alt", &alt).unwrap();
+        }
+        Ok(())
+    })];
+    let output_html = rewrite_str(
+        post_html,
+        RewriteStrSettings {
+            element_content_handlers,
+            ..RewriteStrSettings::new()
+        },
+    )
+    .unwrap();
+    output_html
+}
+
+/// Returns the src of each image in `html` without alt text.
+pub fn images_missing_alt(html: &str) -> Vec<String> {
+    let missing: RefCell<Vec<String>> = RefCell::new(Vec::new());
+    let element_content_handlers = vec![element!("img", |el| {
+        if el
+            .get_attribute("alt")
+            .is_none_or(|alt| alt.trim().is_empty())
+        {
+            missing
+                .borrow_mut()
+                .push(el.get_attribute("src").unwrap_or_default());
+        }
+        Ok(())
+    })];
+    rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers,
+            ..RewriteStrSettings::new()
+        },
+    )
+    .unwrap();
+    missing.into_inner()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alt_from_filename_skips_camera_names() {
+        assert_eq!(
+            alt_from_filename("https://x/great-blue-heron_2.jpg?sz=100"),
+            Some(String::from("great blue heron 2"))
+        );
+        assert_eq!(alt_from_filename("https://x/IMG_1234.JPG"), None);
+        assert_eq!(alt_from_filename("https://x/AAuE7mB3xY9kQ2zT0pLw"), None);
+    }
+
+    #[test]
+    fn add_alt_text_prefers_caption() {
+        let html = r#"<img src="a/001.jpeg" id="img-1"><img src="a/002.jpeg" id="img-2" alt="kept"><img src="a/003.jpeg" id="img-3"><img src="https://x/y">"#;
+        let urls = vec![
+            String::from("https://x/heron.jpg"),
+            String::from("https://x/b.jpg"),
+            String::from("https://x/lac-blanc.jpg"),
+        ];
+        let output = add_alt_text(
+            html,
+            &[Some(String::from("Ardea herodias")), None, None],
+            &urls,
+        );
+        assert!(output.contains(r#"id="img-1" alt="Ardea herodias""#));
+        assert!(output.contains(r#"id="img-2" alt="kept""#));
+        assert!(output.contains(r#"id="img-3" alt="lac blanc""#));
+        assert_eq!(images_missing_alt(&output), vec!["https://x/y"]);
+    }
+}
@@ -0,0 +1,149 @@
+// Per-author pages and an index of authors.
+
+use crate::layout;
+use crate::{normalize_key, Page, SiteOptions};
+
+use htmlize::{escape_attribute, escape_text};
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const AUTHORS_INDEX_FILE: &str = "authors.html";
+
+/// Posts attributed to a single author.
+pub struct Author<'a> {
+    pub name: String,
+    /// Name within output dir.
+    pub output_file: String,
+    /// Posts in the same order as the site.
+    pub pages: Vec<&'a Page>,
+}
+
+/// Loads a JSON file mapping each canonical author name to its other spellings, e.g.
+/// `{"Jane Doe": ["jane", "J. Doe"]}`, and inverts it for lookup via `canonical_author`.
+pub fn load_aliases(path: &Path) -> Result<HashMap<String, String>, io::Error> {
+    let spellings: HashMap<String, Vec<String>> = serde_json::from_slice(&fs::read(path)?)?;
+    let mut aliases: HashMap<String, String> = HashMap::new();
+    for (canonical, others) in spellings {
+        for other in others {
+            aliases.insert(normalize_key(&other), canonical.clone());
+        }
+        aliases.insert(normalize_key(&canonical), canonical);
+    }
+    Ok(aliases)
+}
+
+/// Maps `name` to its canonical spelling, if it has one.
+pub fn canonical_author(name: &str, aliases: &HashMap<String, String>) -> String {
+    match aliases.get(&normalize_key(name)) {
+        Some(canonical) => canonical.clone(),
+        None => String::from(name.trim()),
+    }
+}
+
+/// Groups pages by author, with the most prolific authors first.
+pub fn group_by_author(pages: &[Page]) -> Vec<Author<'_>> {
+    let mut by_name: HashMap<&str, Vec<&Page>> = HashMap::new();
+    for page in pages {
+        if let Some(author) = &page.author {
+            by_name.entry(author.as_str()).or_default().push(page);
+        }
+    }
+    let mut authors: Vec<Author> = by_name
+        .into_iter()
+        .map(|(name, pages)| Author {
+            name: String::from(name),
+            output_file: format!("author_{}.html", crate::make_basename(name, name)),
+            pages,
+        })
+        .collect();
+    authors.sort_by(|a, b| {
+        b.pages
+            .len()
+            .cmp(&a.pages.len())
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    authors
+}
+
+fn make_authors_index_html(authors: &[Author], options: &SiteOptions) -> String {
+    let mut body = String::from("<ul>\n");
+    for author in authors {
+        body.push_str(&format!(
+            "<li><a href=\"{href}\">{name}</a> ({count})</li>\n",
+            href = escape_attribute(&author.output_file),
+            name = escape_text(&author.name),
+            count = author.pages.len(),
+        ));
+    }
+    body.push_str("</ul>");
+    layout::render_page(options.locale.catalog().authors, &body, options)
+}
+
+fn make_author_html(author: &Author, options: &SiteOptions) -> String {
+    let body = format!(
+        r#"<nav><a href="{index}">{all_authors}</a></nav>
+        {posts}"#,
+        index = AUTHORS_INDEX_FILE,
+        all_authors = options.locale.catalog().all_authors,
+        posts = layout::render_post_list(&author.pages, options),
+    );
+    layout::render_page(&author.name, &body, options)
+}
+
+/// Writes a page per author plus the authors index, returning the number of authors.
+pub fn write_author_pages(
+    pages: &[Page],
+    output_dir: &Path,
+    options: &SiteOptions,
+) -> Result<usize, io::Error> {
+    let authors = group_by_author(pages);
+    for author in &authors {
+        fs::write(
+            output_dir.join(&author.output_file),
+            make_author_html(author, options),
+        )?;
+    }
+    fs::write(
+        output_dir.join(AUTHORS_INDEX_FILE),
+        make_authors_index_html(&authors, options),
+    )?;
+    Ok(authors.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page_by(author: &str, title: &str) -> Page {
+        Page {
+            author: Some(String::from(author)),
+            title: String::from(title),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn canonical_author_ignores_case_and_spacing() {
+        let aliases = HashMap::from([(String::from("j. doe"), String::from("Jane Doe"))]);
+        assert_eq!(canonical_author(" J.  Doe", &aliases), "Jane Doe");
+        assert_eq!(canonical_author("John Roe ", &aliases), "John Roe");
+    }
+
+    #[test]
+    fn group_by_author_most_posts_first() {
+        let pages = vec![
+            page_by("Bob", "a"),
+            page_by("Alice", "b"),
+            page_by("Bob", "c"),
+            page_by("Carol", "d"),
+        ];
+        let authors = group_by_author(&pages);
+        let names: Vec<&str> = authors.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, vec!["Bob", "Alice", "Carol"]);
+        let bob_titles: Vec<&str> = authors[0].pages.iter().map(|p| p.title.as_str()).collect();
+        assert_eq!(bob_titles, vec!["a", "c"]);
+    }
+}
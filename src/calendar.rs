@@ -0,0 +1,258 @@
+// Calendar views of posts: a grid per year with links to each day's posts, and an
+// "on this day" page grouping posts from the same month and day across years.
+
+use crate::locale::Catalog;
+use crate::{layout, Page, SiteOptions};
+
+use chrono::{Datelike, NaiveDate};
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const CALENDAR_FILE: &str = "calendar.html";
+pub const ON_THIS_DAY_FILE: &str = "on_this_day.html";
+
+pub fn year_file(year: i32) -> String {
+    format!("calendar_{year}.html")
+}
+
+pub fn day_file(date: NaiveDate) -> String {
+    format!("day_{}.html", date.format("%Y_%m_%d"))
+}
+
+/// Anchor within ON_THIS_DAY_FILE for a month and day.
+fn month_day_anchor(month: u32, day: u32) -> String {
+    format!("d-{month:02}-{day:02}")
+}
+
+/// Pages keyed by post date, keeping the site's order within each day.
+type PagesByDate<'a> = BTreeMap<NaiveDate, Vec<&'a Page>>;
+
+fn group_by_date(pages: &[Page]) -> PagesByDate<'_> {
+    let mut by_date: PagesByDate = BTreeMap::new();
+    for page in pages {
+        by_date.entry(page.post_date).or_default().push(page);
+    }
+    by_date
+}
+
+fn make_month_html(year: i32, month: u32, by_date: &PagesByDate, text: &Catalog) -> String {
+    let first = NaiveDate::from_ymd_opt(year, month, 1).unwrap();
+    let mut html = format!(
+        "<table class=\"month\">\n<caption>{}</caption>\n<tr>",
+        text.format_month(first)
+    );
+    for label in text.weekday_labels {
+        html.push_str(&format!("<th>{label}</th>"));
+    }
+    html.push_str("</tr>\n<tr>");
+    let leading_blanks = first.weekday().num_days_from_monday();
+    for _ in 0..leading_blanks {
+        html.push_str("<td></td>");
+    }
+    let mut column = leading_blanks;
+    let mut date = first;
+    while date.month() == month {
+        if column == 7 {
+            html.push_str("</tr>\n<tr>");
+            column = 0;
+        }
+        match by_date.get(&date) {
+            Some(day_pages) => html.push_str(&format!(
+                "<td><a href=\"{}\" title=\"{}\">{}</a><br><small>{}</small></td>",
+                day_file(date),
+                text.format_post_count(day_pages.len()),
+                date.day(),
+                day_pages.len()
+            )),
+            None => html.push_str(&format!("<td>{}</td>", date.day())),
+        }
+        column += 1;
+        date = date.succ_opt().unwrap();
+    }
+    html.push_str("</tr>\n</table>\n");
+    html
+}
+
+fn make_year_html(
+    year: i32,
+    years: &[i32],
+    by_date: &PagesByDate,
+    options: &SiteOptions,
+) -> String {
+    let text = options.locale.catalog();
+    let year_links: Vec<String> = years
+        .iter()
+        .map(|&other| {
+            if other == year {
+                format!("<strong>{other}</strong>")
+            } else {
+                format!(r#"<a href="{}">{other}</a>"#, year_file(other))
+            }
+        })
+        .collect();
+    let mut body = format!(
+        "<nav>{} | <a href=\"{}\">{}</a></nav>\n",
+        year_links.join(" "),
+        ON_THIS_DAY_FILE,
+        text.on_this_day
+    );
+    for month in 1..=12 {
+        body.push_str(&make_month_html(year, month, by_date, text));
+    }
+    layout::render_page(&year.to_string(), &body, options)
+}
+
+fn make_calendar_index_html(years: &[(i32, usize)], options: &SiteOptions) -> String {
+    let text = options.locale.catalog();
+    let mut body = format!(
+        "<nav><a href=\"{}\">{}</a></nav>\n<ul>\n",
+        ON_THIS_DAY_FILE, text.on_this_day
+    );
+    for (year, count) in years {
+        body.push_str(&format!(
+            "<li><a href=\"{}\">{year}</a> ({count})</li>\n",
+            year_file(*year)
+        ));
+    }
+    body.push_str("</ul>");
+    layout::render_page(text.calendar, &body, options)
+}
+
+fn make_day_html(date: NaiveDate, day_pages: &[&Page], options: &SiteOptions) -> String {
+    let text = options.locale.catalog();
+    let body = format!(
+        "<nav><a href=\"{year_href}\">{year}</a> | <a href=\"{on_this_day}#{anchor}\">{label}</a></nav>\n        {posts}",
+        year_href = year_file(date.year()),
+        year = date.year(),
+        on_this_day = ON_THIS_DAY_FILE,
+        anchor = month_day_anchor(date.month(), date.day()),
+        label = text.on_this_day,
+        posts = layout::render_post_list(day_pages, options),
+    );
+    layout::render_page(&options.format_date(date), &body, options)
+}
+
+/// Script which jumps to today's section of the "on this day" page.
+const ON_THIS_DAY_SCRIPT: &str = r#"<script>
+(function () {
+  if (location.hash) return;
+  const now = new Date();
+  const pad = (n) => String(n).padStart(2, '0');
+  const today = document.getElementById('d-' + pad(now.getMonth() + 1) + '-' + pad(now.getDate()));
+  if (today) today.scrollIntoView();
+})();
+</script>"#;
+
+fn make_on_this_day_html(by_date: &PagesByDate, options: &SiteOptions) -> String {
+    let text = options.locale.catalog();
+    // Group by (month, day), then by year, most recent year first.
+    let mut by_month_day: BTreeMap<(u32, u32), Vec<NaiveDate>> = BTreeMap::new();
+    for date in by_date.keys().rev() {
+        by_month_day
+            .entry((date.month(), date.day()))
+            .or_default()
+            .push(*date);
+    }
+    let mut body = String::new();
+    for ((month, day), dates) in by_month_day {
+        body.push_str(&format!(
+            "<h2 id=\"{}\">{}</h2>\n",
+            month_day_anchor(month, day),
+            text.format_month_day(dates[0])
+        ));
+        for date in dates {
+            body.push_str(&format!(
+                "<h3><a href=\"{}\">{}</a></h3>\n{}\n",
+                day_file(date),
+                date.year(),
+                layout::render_post_list(&by_date[&date], options)
+            ));
+        }
+    }
+    body.push_str(ON_THIS_DAY_SCRIPT);
+    layout::render_page(text.on_this_day, &body, options)
+}
+
+/// Writes the calendar index, a page per year, a page per day with posts, and the
+/// "on this day" page.
+pub fn write_calendar_pages(
+    pages: &[Page],
+    output_dir: &Path,
+    options: &SiteOptions,
+) -> Result<(), io::Error> {
+    let by_date = group_by_date(pages);
+    let mut year_counts: BTreeMap<i32, usize> = BTreeMap::new();
+    for (date, day_pages) in &by_date {
+        *year_counts.entry(date.year()).or_default() += day_pages.len();
+        fs::write(
+            output_dir.join(day_file(*date)),
+            make_day_html(*date, day_pages, options),
+        )?;
+    }
+    let years: Vec<(i32, usize)> = year_counts.into_iter().rev().collect();
+    let year_numbers: Vec<i32> = years.iter().map(|(year, _)| *year).collect();
+    for &year in &year_numbers {
+        fs::write(
+            output_dir.join(year_file(year)),
+            make_year_html(year, &year_numbers, &by_date, options),
+        )?;
+    }
+    fs::write(
+        output_dir.join(CALENDAR_FILE),
+        make_calendar_index_html(&years, options),
+    )?;
+    fs::write(
+        output_dir.join(ON_THIS_DAY_FILE),
+        make_on_this_day_html(&by_date, options),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locale::Locale;
+
+    fn page_on(year: i32, month: u32, day: u32) -> Page {
+        Page {
+            post_date: NaiveDate::from_ymd_opt(year, month, day).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn month_grid_starts_on_weekday() {
+        let pages = vec![page_on(2023, 7, 19), page_on(2023, 7, 19)];
+        let by_date = group_by_date(&pages);
+        let html = make_month_html(2023, 7, &by_date, Locale::En.catalog());
+        // July 1, 2023 was a Saturday.
+        assert!(html.contains(
+            "<tr><td></td><td></td><td></td><td></td><td></td><td>1</td><td>2</td></tr>"
+        ));
+        assert!(html.contains(
+            r#"<td><a href="day_2023_07_19.html" title="2 posts">19</a><br><small>2</small></td>"#
+        ));
+        assert!(html.contains("<td>31</td></tr>\n</table>"));
+    }
+
+    #[test]
+    fn on_this_day_groups_across_years() {
+        let pages = vec![
+            page_on(2024, 7, 19),
+            page_on(2023, 1, 2),
+            page_on(2022, 7, 19),
+        ];
+        let html = make_on_this_day_html(&group_by_date(&pages), &Default::default());
+        let jan = html.find(r#"<h2 id="d-01-02">January 2</h2>"#).unwrap();
+        let jul = html.find(r#"<h2 id="d-07-19">July 19</h2>"#).unwrap();
+        let y2024 = html
+            .find(r#"<a href="day_2024_07_19.html">2024</a>"#)
+            .unwrap();
+        let y2022 = html
+            .find(r#"<a href="day_2022_07_19.html">2022</a>"#)
+            .unwrap();
+        assert!(jan < jul && jul < y2024 && y2024 < y2022);
+    }
+}
@@ -0,0 +1,197 @@
+// Detection of photos reposted in several posts, possibly resized or recompressed, by comparing
+// perceptual hashes.
+
+use crate::locale;
+use crate::{layout, Page, SiteOptions};
+
+use htmlize::{escape_attribute, escape_text};
+use image::imageops::FilterType;
+use image::DynamicImage;
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const DUPLICATES_FILE: &str = "duplicates.html";
+
+/// Largest number of bits by which the hashes of two images can differ for them to be treated
+/// as the same photo.
+const MAX_DISTANCE: u32 = 10;
+
+/// An image, as the index of its post within the pages and its index within the post's images.
+pub type ImageRef = (usize, usize);
+
+/// Computes a difference hash: each bit says whether a pixel of the image shrunk to 9x8 in
+/// grayscale is brighter than the one to its right, which survives resizing and recompression.
+pub fn perceptual_hash(image: &DynamicImage) -> u64 {
+    let small = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            let brighter = small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | u64::from(brighter);
+        }
+    }
+    hash
+}
+
+/// Groups images whose hashes are close to that of the group's first image, keeping the groups
+/// which span more than one post. Comparing with the first image rather than with any member
+/// keeps a chain of slightly different images from joining unrelated ones. Groups are in order
+/// of their first image, and images within them in order of the pages.
+pub fn find_clusters(pages: &[Page]) -> Vec<Vec<ImageRef>> {
+    let images: Vec<(ImageRef, u64)> = pages
+        .iter()
+        .enumerate()
+        .flat_map(|(page_index, page)| {
+            page.image_hashes
+                .iter()
+                .enumerate()
+                .filter_map(move |(index, hash)| Some(((page_index, index), (*hash)?)))
+        })
+        // Images without any edges, such as blank ones, all hash to 0 without being alike.
+        .filter(|(_, hash)| *hash != 0)
+        .collect();
+    let mut groups: Vec<(u64, Vec<ImageRef>)> = Vec::new();
+    for (image, hash) in images {
+        let group = groups
+            .iter_mut()
+            .find(|(first_hash, _)| (first_hash ^ hash).count_ones() <= MAX_DISTANCE);
+        match group {
+            Some((_, group)) => group.push(image),
+            None => groups.push((hash, vec![image])),
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(_, group)| group)
+        .filter(|group| {
+            let posts: HashSet<usize> = group.iter().map(|(page_index, _)| *page_index).collect();
+            posts.len() > 1
+        })
+        .collect()
+}
+
+/// Maps each image in `clusters` to the first image of the same cluster in each other post.
+pub fn other_appearances(clusters: &[Vec<ImageRef>]) -> HashMap<ImageRef, Vec<ImageRef>> {
+    let mut appearances: HashMap<ImageRef, Vec<ImageRef>> = HashMap::new();
+    for cluster in clusters {
+        for image in cluster {
+            let mut others: Vec<ImageRef> = Vec::new();
+            for other in cluster {
+                let is_new_post = other.0 != image.0
+                    && others.last().is_none_or(|previous| previous.0 != other.0);
+                if is_new_post {
+                    others.push(*other);
+                }
+            }
+            appearances.insert(*image, others);
+        }
+    }
+    appearances
+}
+
+fn make_report_html(pages: &[Page], clusters: &[Vec<ImageRef>], options: &SiteOptions) -> String {
+    let text = options.locale.catalog();
+    let mut body = format!(
+        "<p>{}</p>\n",
+        escape_text(locale::fill(
+            text.duplicate_count,
+            &[("count", &clusters.len().to_string())]
+        ))
+    );
+    for cluster in clusters {
+        body.push_str("        <p class=\"gallery\">\n");
+        for (page_index, index) in cluster {
+            let page = &pages[*page_index];
+            let title = format!("{} ({})", page.title, options.format_date(page.post_date));
+            let img_html = format!(
+                "<img src=\"{src}\" alt=\"{alt}\">",
+                src = escape_attribute(&page.thumbnails[*index]),
+                alt = escape_attribute(&title),
+            );
+            let alternative = page.thumbnail_alternatives.get(*index).cloned().flatten();
+            body.push_str(&format!(
+                "<a href=\"{href}#img-{n}\" title=\"{title}\">{picture}</a>\n",
+                href = escape_attribute(&page.output_file),
+                n = index + 1,
+                title = escape_attribute(&title),
+                picture = layout::picture_html(&img_html, alternative.as_deref()),
+            ));
+        }
+        body.push_str("</p>\n");
+    }
+    layout::render_page(text.duplicate_photos, &body, options)
+}
+
+/// Writes a page listing `clusters` of the same photo in different posts, for finding reposts.
+pub fn write_report(
+    pages: &[Page],
+    clusters: &[Vec<ImageRef>],
+    output_dir: &Path,
+    options: &SiteOptions,
+) -> Result<(), io::Error> {
+    fs::write(
+        output_dir.join(DUPLICATES_FILE),
+        make_report_html(pages, clusters, options),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page_with_hashes(hashes: &[u64]) -> Page {
+        Page {
+            image_hashes: hashes.iter().map(|hash| Some(*hash)).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn perceptual_hash_survives_resizing() {
+        let image = DynamicImage::ImageRgb8(image::RgbImage::from_fn(400, 300, |x, y| {
+            image::Rgb([
+                (x * 255 / 400) as u8,
+                (y * 255 / 300) as u8,
+                ((x * y) % 256) as u8,
+            ])
+        }));
+        let smaller = image.resize(120, 90, FilterType::Lanczos3);
+        let flipped = image.fliph();
+        let distance = |a: &DynamicImage, b: &DynamicImage| {
+            (perceptual_hash(a) ^ perceptual_hash(b)).count_ones()
+        };
+        assert!(distance(&image, &smaller) <= MAX_DISTANCE);
+        assert!(distance(&image, &flipped) > MAX_DISTANCE);
+    }
+
+    #[test]
+    fn find_clusters_spans_posts() {
+        let pages = vec![
+            page_with_hashes(&[0xFF00_FF00_FF00_FF00, 0x1234_5678_9ABC_DEF0]),
+            // Differs from the first image of the first post by 2 bits.
+            page_with_hashes(&[0xFF00_FF00_FF00_FF03]),
+            // Alike, but within one post.
+            page_with_hashes(&[0x0F0F_0F0F_0F0F_0F0F, 0x0F0F_0F0F_0F0F_0F0E]),
+        ];
+        let clusters = find_clusters(&pages);
+        assert_eq!(clusters, vec![vec![(0, 0), (1, 0)]]);
+        assert_eq!(other_appearances(&clusters)[&(1, 0)], vec![(0, 0)]);
+    }
+
+    #[test]
+    fn find_clusters_doesnt_chain() {
+        let first = 0xFF00_FF00_FF00_FF00;
+        // Each differs from the one before by 6 bits, so the last differs from the first by 12.
+        let second = first ^ 0x3F;
+        let third = second ^ 0x3F00;
+        let pages = vec![
+            page_with_hashes(&[first]),
+            page_with_hashes(&[second]),
+            page_with_hashes(&[third]),
+        ];
+        assert_eq!(find_clusters(&pages), vec![vec![(0, 0), (1, 0)]]);
+    }
+}
@@ -0,0 +1,344 @@
+// Atom, RSS and JSON feeds of the most recent posts.
+
+use crate::{Page, SiteOptions};
+
+use chrono::{DateTime, FixedOffset, NaiveTime, Utc};
+use htmlize::{escape_attribute, escape_text};
+use serde_derive::Serialize;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const ATOM_FILE: &str = "feed.atom";
+pub const RSS_FILE: &str = "rss.xml";
+pub const JSON_FEED_FILE: &str = "feed.json";
+
+const JSON_FEED_VERSION: &str = "https://jsonfeed.org/version/1.1";
+
+/// Feeds only include this many of the most recent posts.
+const MAX_FEED_ENTRIES: usize = 50;
+
+/// When a page was posted, treating it as midnight UTC if the time of day isn't known.
+fn page_datetime(page: &Page, options: &SiteOptions) -> DateTime<FixedOffset> {
+    options.post_datetime(page).unwrap_or_else(|| {
+        page.post_date
+            .and_time(NaiveTime::MIN)
+            .and_utc()
+            .fixed_offset()
+    })
+}
+
+pub fn image_mime_type(path: &str) -> &'static str {
+    match path.rsplit('.').next().map(|ext| ext.to_ascii_lowercase()) {
+        Some(ext) if ext == "png" => "image/png",
+        Some(ext) if ext == "gif" => "image/gif",
+        Some(ext) if ext == "webp" => "image/webp",
+        Some(ext) if ext == "avif" => "image/avif",
+        Some(ext) if ext == "svg" => "image/svg+xml",
+        _ => "image/jpeg",
+    }
+}
+
+/// URL, MIME type and size in bytes of the first thumbnail of a page.
+fn enclosure(
+    page: &Page,
+    output_dir: &Path,
+    options: &SiteOptions,
+) -> Option<(String, String, u64)> {
+    let thumbnail = page.thumbnails.first()?;
+    let length = fs::metadata(output_dir.join(thumbnail)).map_or(0, |m| m.len());
+    Some((
+        options.url_for(thumbnail),
+        String::from(image_mime_type(thumbnail)),
+        length,
+    ))
+}
+
+/// Atom requires the feed's id to be an absolute IRI. Without a base URL, the Google Group the
+/// posts came from is used, as the URL of the most recent post up to its "/c/" part.
+fn atom_feed_id(pages: &[Page], options: &SiteOptions) -> String {
+    match (&options.base_url, pages.first()) {
+        (None, Some(page)) => match page.original_url.split_once("/c/") {
+            Some((group_url, _)) => String::from(group_url),
+            None => page.original_url.clone(),
+        },
+        _ => options.url_for(ATOM_FILE),
+    }
+}
+
+fn make_atom(pages: &[Page], output_dir: &Path, options: &SiteOptions) -> String {
+    let updated = pages
+        .iter()
+        .map(|page| page_datetime(page, options))
+        .max()
+        .unwrap_or_else(|| Utc::now().fixed_offset());
+    let mut xml = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>{title}</title>
+  <id>{id}</id>
+  <updated>{updated}</updated>
+  <author><name>{title}</name></author>
+  <link rel="self" href="{self_href}"/>
+  <generator>{generator}</generator>
+"#,
+        title = escape_text(&options.site_title),
+        id = escape_text(atom_feed_id(pages, options)),
+        updated = updated.to_rfc3339(),
+        self_href = escape_attribute(options.url_for(ATOM_FILE)),
+        generator = env!("CARGO_PKG_NAME"),
+    );
+    // An empty href would be taken as a link to the feed itself.
+    if options.base_url.is_some() {
+        xml.push_str(&format!(
+            "  <link href=\"{}\"/>\n",
+            escape_attribute(options.url_for(""))
+        ));
+    }
+    for page in pages.iter().take(MAX_FEED_ENTRIES) {
+        xml.push_str(&format!(
+            r#"  <entry>
+    <title>{title}</title>
+    <id>{id}</id>
+    <link href="{href}"/>
+    <published>{date}</published>
+    <updated>{date}</updated>
+    <summary>{summary}</summary>
+"#,
+            title = escape_text(&page.title),
+            id = escape_text(&page.original_url),
+            href = escape_attribute(options.url_for(&page.output_file)),
+            date = page_datetime(page, options).to_rfc3339(),
+            summary = escape_text(&page.initial_text),
+        ));
+        if let Some(author) = &page.author {
+            xml.push_str(&format!(
+                "    <author><name>{}</name></author>\n",
+                escape_text(author)
+            ));
+        }
+        if let Some((url, mime_type, length)) = enclosure(page, output_dir, options) {
+            xml.push_str(&format!(
+                "    <link rel=\"enclosure\" href=\"{}\" type=\"{}\" length=\"{}\"/>\n",
+                escape_attribute(&url),
+                mime_type,
+                length
+            ));
+        }
+        xml.push_str("  </entry>\n");
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+fn make_rss(pages: &[Page], output_dir: &Path, options: &SiteOptions) -> String {
+    let mut xml = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0" xmlns:dc="http://purl.org/dc/elements/1.1/">
+  <channel>
+    <title>{title}</title>
+    <link>{home}</link>
+    <description>{title}</description>
+    <generator>{generator}</generator>
+"#,
+        title = escape_text(&options.site_title),
+        home = escape_text(options.url_for("")),
+        generator = env!("CARGO_PKG_NAME"),
+    );
+    for page in pages.iter().take(MAX_FEED_ENTRIES) {
+        xml.push_str(&format!(
+            r#"    <item>
+      <title>{title}</title>
+      <link>{link}</link>
+      <guid isPermaLink="false">{guid}</guid>
+      <pubDate>{date}</pubDate>
+      <description>{summary}</description>
+"#,
+            title = escape_text(&page.title),
+            link = escape_text(options.url_for(&page.output_file)),
+            guid = escape_text(&page.original_url),
+            date = page_datetime(page, options).to_rfc2822(),
+            summary = escape_text(&page.initial_text),
+        ));
+        if let Some(author) = &page.author {
+            xml.push_str(&format!(
+                "      <dc:creator>{}</dc:creator>\n",
+                escape_text(author)
+            ));
+        }
+        if let Some((url, mime_type, length)) = enclosure(page, output_dir, options) {
+            xml.push_str(&format!(
+                "      <enclosure url=\"{}\" type=\"{}\" length=\"{}\"/>\n",
+                escape_attribute(&url),
+                mime_type,
+                length
+            ));
+        }
+        xml.push_str("    </item>\n");
+    }
+    xml.push_str("  </channel>\n</rss>\n");
+    xml
+}
+
+#[derive(Serialize)]
+struct JsonFeedAuthor<'a> {
+    name: &'a str,
+}
+
+#[derive(Serialize)]
+struct JsonFeedItem<'a> {
+    id: &'a str,
+    url: String,
+    title: &'a str,
+    /// Required, unlike the other content fields.
+    content_html: &'a str,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    summary: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image: Option<String>,
+    date_published: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    authors: Vec<JsonFeedAuthor<'a>>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    tags: &'a [String],
+}
+
+/// See https://www.jsonfeed.org/version/1.1/.
+#[derive(Serialize)]
+struct JsonFeed<'a> {
+    version: &'static str,
+    title: &'a str,
+    home_page_url: String,
+    feed_url: String,
+    items: Vec<JsonFeedItem<'a>>,
+}
+
+fn make_json_feed(pages: &[Page], options: &SiteOptions) -> Result<String, io::Error> {
+    let feed = JsonFeed {
+        version: JSON_FEED_VERSION,
+        title: &options.site_title,
+        home_page_url: options.url_for(""),
+        feed_url: options.url_for(JSON_FEED_FILE),
+        items: pages
+            .iter()
+            .take(MAX_FEED_ENTRIES)
+            .map(|page| JsonFeedItem {
+                id: &page.original_url,
+                url: options.url_for(&page.output_file),
+                title: &page.title,
+                content_html: &page.post_html,
+                summary: &page.initial_text,
+                image: page.thumbnails.first().map(|t| options.url_for(t)),
+                date_published: page_datetime(page, options).to_rfc3339(),
+                authors: page
+                    .author
+                    .iter()
+                    .map(|name| JsonFeedAuthor { name })
+                    .collect(),
+                tags: &page.i_text,
+            })
+            .collect(),
+    };
+    Ok(serde_json::to_string_pretty(&feed)?)
+}
+
+/// Writes the Atom, RSS and JSON feeds for `pages`, which should be sorted with the most recent first.
+pub fn write_feeds(
+    pages: &[Page],
+    output_dir: &Path,
+    options: &SiteOptions,
+) -> Result<(), io::Error> {
+    fs::write(
+        output_dir.join(ATOM_FILE),
+        make_atom(pages, output_dir, options),
+    )?;
+    fs::write(
+        output_dir.join(RSS_FILE),
+        make_rss(pages, output_dir, options),
+    )?;
+    fs::write(
+        output_dir.join(JSON_FEED_FILE),
+        make_json_feed(pages, options)?,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::NaiveDate;
+
+    #[test]
+    fn rss_entry_has_absolute_link_and_author() {
+        let options = SiteOptions {
+            base_url: Some(String::from("https://example.com/birds/")),
+            site_title: String::from("Birds & more"),
+            ..Default::default()
+        };
+        let pages = vec![Page {
+            title: String::from("Heron <3"),
+            author: Some(String::from("Jane")),
+            output_file: String::from("heron_1.html"),
+            scrape_date: DateTime::parse_from_rfc3339("2023-07-20T09:00:00+02:00").unwrap(),
+            post_date: NaiveDate::from_ymd_opt(2023, 7, 19).unwrap(),
+            post_time: NaiveTime::from_hms_opt(14, 30, 0),
+            ..Default::default()
+        }];
+        let rss = make_rss(&pages, Path::new("/nonexistent"), &options);
+        assert!(rss.contains("<title>Birds &amp; more</title>"));
+        assert!(rss.contains("<title>Heron &lt;3</title>"));
+        assert!(rss.contains("<link>https://example.com/birds/heron_1.html</link>"));
+        assert!(rss.contains("<pubDate>Wed, 19 Jul 2023 14:30:00 +0200</pubDate>"));
+        assert!(rss.contains("<dc:creator>Jane</dc:creator>"));
+    }
+
+    #[test]
+    fn atom_without_base_url_has_absolute_id_and_author() {
+        let options = SiteOptions {
+            site_title: String::from("Birds"),
+            ..Default::default()
+        };
+        let pages = vec![Page {
+            title: String::from("Heron"),
+            original_url: String::from("https://groups.google.com/g/birds/c/1"),
+            output_file: String::from("heron_1.html"),
+            post_date: NaiveDate::from_ymd_opt(2023, 7, 19).unwrap(),
+            ..Default::default()
+        }];
+        let atom = make_atom(&pages, Path::new("/nonexistent"), &options);
+        assert!(atom.contains("  <id>https://groups.google.com/g/birds</id>\n"));
+        assert!(atom.contains("  <author><name>Birds</name></author>\n"));
+        assert!(!atom.contains(r#"<link href="""#));
+    }
+
+    #[test]
+    fn json_feed_omits_missing_fields() {
+        let options = SiteOptions {
+            site_title: String::from("Birds"),
+            ..Default::default()
+        };
+        let pages = vec![Page {
+            title: String::from("Heron"),
+            original_url: String::from("https://groups.google.com/g/x/c/1"),
+            output_file: String::from("heron_1.html"),
+            post_date: NaiveDate::from_ymd_opt(2023, 7, 19).unwrap(),
+            post_html: String::from("<p>A heron</p>"),
+            ..Default::default()
+        }];
+        let feed: serde_json::Value =
+            serde_json::from_str(&make_json_feed(&pages, &options).unwrap()).unwrap();
+        assert_eq!(feed["version"], JSON_FEED_VERSION);
+        assert_eq!(
+            feed["items"][0],
+            serde_json::json!({
+                "id": "https://groups.google.com/g/x/c/1",
+                "url": "heron_1.html",
+                "title": "Heron",
+                "content_html": "<p>A heron</p>",
+                "date_published": "2023-07-19T00:00:00+00:00",
+            })
+        );
+    }
+}
@@ -0,0 +1,209 @@
+// A site-wide gallery of every thumbnail, most recent first, split across pages.
+
+use crate::locale::Catalog;
+use crate::{duplicates, layout, Page, SiteOptions};
+
+use htmlize::{escape_attribute, escape_text};
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const GALLERY_FILE: &str = "gallery.html";
+
+/// Number of thumbnails on each gallery page.
+const GALLERY_PAGE_SIZE: usize = 120;
+
+/// A thumbnail along with the post it came from.
+struct GalleryItem<'a> {
+    page: &'a Page,
+    /// Index within page.images.
+    index: usize,
+    /// Other posts with the same photo, and the index of the photo within each.
+    also_in: Vec<(&'a Page, usize)>,
+}
+
+/// Name within output dir of the gallery page with the given 1-based number.
+pub fn gallery_file(number: usize) -> String {
+    if number == 1 {
+        String::from(GALLERY_FILE)
+    } else {
+        format!("gallery_{number}.html")
+    }
+}
+
+fn make_pagination_html(number: usize, num_pages: usize, text: &Catalog) -> String {
+    if num_pages <= 1 {
+        return String::new();
+    }
+    let mut links: Vec<String> = Vec::new();
+    if number > 1 {
+        links.push(format!(
+            r#"<a href="{}" rel="prev">{}</a>"#,
+            gallery_file(number - 1),
+            text.newer
+        ));
+    }
+    for other in 1..=num_pages {
+        if other == number {
+            links.push(format!("<strong>{other}</strong>"));
+        } else {
+            links.push(format!(r#"<a href="{}">{other}</a>"#, gallery_file(other)));
+        }
+    }
+    if number < num_pages {
+        links.push(format!(
+            r#"<a href="{}" rel="next">{}</a>"#,
+            gallery_file(number + 1),
+            text.older
+        ));
+    }
+    format!("<nav>{}</nav>", links.join(" "))
+}
+
+fn make_gallery_html(
+    items: &[GalleryItem],
+    number: usize,
+    num_pages: usize,
+    options: &SiteOptions,
+) -> String {
+    let text = options.locale.catalog();
+    let pagination = make_pagination_html(number, num_pages, text);
+    let mut body = format!("{pagination}\n        <p class=\"gallery\">\n");
+    for item in items {
+        let page = item.page;
+        let caption = page
+            .image_captions
+            .get(item.index)
+            .cloned()
+            .flatten()
+            .unwrap_or_else(|| page.title.clone());
+        let img_html = format!(
+            "<img src=\"{src}\" alt=\"{alt}\">",
+            src = escape_attribute(&page.thumbnails[item.index]),
+            alt = escape_attribute(&caption),
+        );
+        let alternative = page
+            .thumbnail_alternatives
+            .get(item.index)
+            .cloned()
+            .flatten();
+        body.push_str(&format!(
+            "<a href=\"{href}#img-{n}\" title=\"{title}\">{picture}</a>\n",
+            href = escape_attribute(&page.output_file),
+            n = item.index + 1,
+            title = escape_attribute(format!(
+                "{} ({})",
+                page.title,
+                options.format_date(page.post_date)
+            )),
+            picture = layout::picture_html(&img_html, alternative.as_deref()),
+        ));
+        if !item.also_in.is_empty() {
+            let links: Vec<String> = item
+                .also_in
+                .iter()
+                .map(|(other, index)| {
+                    format!(
+                        "<a href=\"{}#img-{}\">{}</a>",
+                        escape_attribute(&other.output_file),
+                        index + 1,
+                        escape_text(&other.title)
+                    )
+                })
+                .collect();
+            body.push_str(&format!(
+                "<small class=\"also-in\">{} {}</small>\n",
+                text.also_in,
+                links.join(", ")
+            ));
+        }
+    }
+    body.push_str("</p>\n        ");
+    body.push_str(&pagination);
+    let title = if num_pages > 1 {
+        format!("{} ({number}/{num_pages})", text.gallery)
+    } else {
+        String::from(text.gallery)
+    };
+    layout::render_page(&title, &body, options)
+}
+
+/// Writes the gallery pages for `pages`, which should be sorted with the most recent first,
+/// returning the number of gallery pages. `clusters` are the duplicate photos found in them.
+pub fn write_gallery_pages(
+    pages: &[Page],
+    clusters: &[Vec<duplicates::ImageRef>],
+    output_dir: &Path,
+    options: &SiteOptions,
+) -> Result<usize, io::Error> {
+    let appearances = if options.mark_duplicates {
+        duplicates::other_appearances(clusters)
+    } else {
+        HashMap::new()
+    };
+    let mut items: Vec<GalleryItem> = Vec::new();
+    for (page_index, page) in pages.iter().enumerate() {
+        for index in 0..page.thumbnails.len() {
+            let also_in = appearances
+                .get(&(page_index, index))
+                .map(|others| {
+                    others
+                        .iter()
+                        .map(|(other_index, other_image)| (&pages[*other_index], *other_image))
+                        .collect()
+                })
+                .unwrap_or_default();
+            items.push(GalleryItem {
+                page,
+                index,
+                also_in,
+            });
+        }
+    }
+    let chunks: Vec<&[GalleryItem]> = if items.is_empty() {
+        vec![&[]]
+    } else {
+        items.chunks(GALLERY_PAGE_SIZE).collect()
+    };
+    for (i, chunk) in chunks.iter().enumerate() {
+        fs::write(
+            output_dir.join(gallery_file(i + 1)),
+            make_gallery_html(chunk, i + 1, chunks.len(), options),
+        )?;
+    }
+    Ok(chunks.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locale::Locale;
+
+    #[test]
+    fn pagination_marks_current_page() {
+        assert_eq!(make_pagination_html(1, 1, Locale::En.catalog()), "");
+        assert_eq!(
+            make_pagination_html(2, 3, Locale::En.catalog()),
+            r#"<nav><a href="gallery.html" rel="prev">Newer</a> <a href="gallery.html">1</a> <strong>2</strong> <a href="gallery_3.html">3</a> <a href="gallery_3.html" rel="next">Older</a></nav>"#
+        );
+    }
+
+    #[test]
+    fn gallery_links_to_image_anchor() {
+        let page = Page {
+            title: String::from("Heron"),
+            output_file: String::from("heron.html"),
+            thumbnails: vec![String::from("a.jpeg"), String::from("b.jpeg")],
+            ..Default::default()
+        };
+        let items = vec![GalleryItem {
+            page: &page,
+            index: 1,
+            also_in: Vec::new(),
+        }];
+        let html = make_gallery_html(&items, 1, 1, &Default::default());
+        assert!(html.contains(r#"<a href="heron.html#img-2" title="Heron (Jan 01, 1970)"><img src="b.jpeg" alt="Heron"></a>"#));
+    }
+}
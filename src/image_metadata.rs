@@ -0,0 +1,203 @@
+// EXIF metadata embedded in extracted images.
+
+use chrono::{NaiveDate, NaiveDateTime};
+use exif::{Exif, In, Reader, Tag, Value};
+use serde_derive::Serialize;
+
+use std::io::Cursor;
+
+/// Where an image was taken, in decimal degrees.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct GpsPosition {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// How and when an image was taken, as far as its EXIF data says.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ImageMetadata {
+    /// When the photo was taken, in the camera's local time.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub taken_at: Option<NaiveDateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub camera_model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lens_model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub focal_length_mm: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub f_number: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exposure_time_s: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iso: Option<u32>,
+    /// Only read when allowed, since it can reveal where members live.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gps: Option<GpsPosition>,
+}
+
+fn ascii(exif: &Exif, tag: Tag) -> Option<String> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Ascii(values) => {
+            let text = String::from_utf8_lossy(values.first()?);
+            let text = text.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+            (!text.is_empty()).then(|| String::from(text))
+        }
+        _ => None,
+    }
+}
+
+fn rationals(exif: &Exif, tag: Tag) -> Option<Vec<f64>> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Rational(values) if !values.is_empty() => {
+            Some(values.iter().map(|value| value.to_f64()).collect())
+        }
+        _ => None,
+    }
+}
+
+fn rational(exif: &Exif, tag: Tag) -> Option<f64> {
+    rationals(exif, tag)?
+        .first()
+        .copied()
+        .filter(|value| value.is_finite() && *value > 0.0)
+}
+
+fn datetime(exif: &Exif, tag: Tag) -> Option<NaiveDateTime> {
+    let Value::Ascii(values) = &exif.get_field(tag, In::PRIMARY)?.value else {
+        return None;
+    };
+    let parsed = exif::DateTime::from_ascii(values.first()?).ok()?;
+    NaiveDate::from_ymd_opt(parsed.year.into(), parsed.month.into(), parsed.day.into())?
+        .and_hms_opt(
+            parsed.hour.into(),
+            parsed.minute.into(),
+            parsed.second.into(),
+        )
+}
+
+/// Converts a GPS coordinate stored as degrees, minutes and seconds plus a reference such as
+/// "S", which makes it negative.
+fn coordinate(exif: &Exif, tag: Tag, ref_tag: Tag, negative_ref: &str) -> Option<f64> {
+    let parts = rationals(exif, tag)?;
+    let [degrees, minutes, seconds] = parts[..] else {
+        return None;
+    };
+    let value = degrees + minutes / 60.0 + seconds / 3600.0;
+    if ascii(exif, ref_tag).as_deref() == Some(negative_ref) {
+        Some(-value)
+    } else {
+        Some(value)
+    }
+}
+
+/// Reads the metadata from an image, which is empty if the image has no readable EXIF data.
+pub fn read(contents: &[u8], include_gps: bool) -> ImageMetadata {
+    let Ok(exif) = Reader::new().read_from_container(&mut Cursor::new(contents)) else {
+        return Default::default();
+    };
+    let gps = match (
+        coordinate(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, "S"),
+        coordinate(&exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, "W"),
+    ) {
+        (Some(latitude), Some(longitude)) if include_gps => Some(GpsPosition {
+            latitude,
+            longitude,
+        }),
+        _ => None,
+    };
+    ImageMetadata {
+        taken_at: datetime(&exif, Tag::DateTimeOriginal)
+            .or_else(|| datetime(&exif, Tag::DateTimeDigitized)),
+        camera_model: ascii(&exif, Tag::Model),
+        lens_model: ascii(&exif, Tag::LensModel),
+        focal_length_mm: rational(&exif, Tag::FocalLength),
+        f_number: rational(&exif, Tag::FNumber),
+        exposure_time_s: rational(&exif, Tag::ExposureTime),
+        iso: exif
+            .get_field(Tag::PhotographicSensitivity, In::PRIMARY)
+            .and_then(|field| field.value.get_uint(0)),
+        gps,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exif::experimental::Writer;
+    use exif::{Field, Rational};
+
+    fn tiff_with(fields: &[Field]) -> Vec<u8> {
+        let mut writer = Writer::new();
+        for field in fields {
+            writer.push_field(field);
+        }
+        let mut contents = Cursor::new(Vec::new());
+        writer.write(&mut contents, false).unwrap();
+        contents.into_inner()
+    }
+
+    fn field(tag: Tag, value: Value) -> Field {
+        Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value,
+        }
+    }
+
+    #[test]
+    fn read_extracts_exposure() {
+        let contents = tiff_with(&[
+            field(
+                Tag::DateTimeOriginal,
+                Value::Ascii(vec![b"2023:07:19 18:31:05".to_vec()]),
+            ),
+            field(Tag::Model, Value::Ascii(vec![b"Pixel 7\0".to_vec()])),
+            field(
+                Tag::FocalLength,
+                Value::Rational(vec![Rational { num: 69, denom: 10 }]),
+            ),
+            field(
+                Tag::ExposureTime,
+                Value::Rational(vec![Rational { num: 1, denom: 250 }]),
+            ),
+            field(Tag::PhotographicSensitivity, Value::Short(vec![400])),
+        ]);
+        let metadata = read(&contents, true);
+        assert_eq!(
+            metadata.taken_at,
+            NaiveDate::from_ymd_opt(2023, 7, 19).and_then(|date| date.and_hms_opt(18, 31, 5))
+        );
+        assert_eq!(metadata.camera_model.as_deref(), Some("Pixel 7"));
+        assert_eq!(metadata.focal_length_mm, Some(6.9));
+        assert_eq!(metadata.exposure_time_s, Some(0.004));
+        assert_eq!(metadata.iso, Some(400));
+        assert_eq!(metadata.gps, None);
+        assert_eq!(read(b"not an image", true), Default::default());
+    }
+
+    #[test]
+    fn read_gps_only_when_allowed() {
+        let degrees = |d, m, s| {
+            Value::Rational(vec![
+                Rational { num: d, denom: 1 },
+                Rational { num: m, denom: 1 },
+                Rational { num: s, denom: 1 },
+            ])
+        };
+        let contents = tiff_with(&[
+            field(Tag::GPSLatitudeRef, Value::Ascii(vec![b"N".to_vec()])),
+            field(Tag::GPSLatitude, degrees(45, 30, 0)),
+            field(Tag::GPSLongitudeRef, Value::Ascii(vec![b"W".to_vec()])),
+            field(Tag::GPSLongitude, degrees(73, 36, 0)),
+        ]);
+        assert_eq!(
+            read(&contents, true).gps,
+            Some(GpsPosition {
+                latitude: 45.5,
+                longitude: -73.6
+            })
+        );
+        assert_eq!(read(&contents, false).gps, None);
+    }
+}
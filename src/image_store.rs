@@ -0,0 +1,368 @@
+// Content-addressed storage for extracted images, so that an image appearing in several posts,
+// such as a repost or a signature, is written and thumbnailed only once.
+
+use crate::image_metadata::{self, ImageMetadata};
+use crate::thumbnail::{self, SizedImage};
+use crate::{duplicates, image_extension, strip_metadata, SiteOptions};
+
+use chrono::NaiveDateTime;
+use sha2::{Digest, Sha256};
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Name within output dir of the directory holding every image.
+pub const IMAGES_DIR: &str = "images";
+
+/// An image in the store, along with everything made from it.
+#[derive(Clone, Debug)]
+pub struct StoredImage {
+    /// Path within output dir, or None if the image was left out because its metadata couldn't
+    /// be stripped.
+    pub path: Option<String>,
+    pub thumbnail: String,
+    /// Path to the thumbnail in WebP or AVIF format, if made.
+    pub thumbnail_alternative: Option<String>,
+    /// Versions by width, narrowest first and ending with the image itself. Empty if the image
+    /// couldn't be decoded.
+    pub versions: Vec<SizedImage>,
+    /// Metadata of the published image, so without anything stripped from it.
+    pub metadata: ImageMetadata,
+    /// When the photo was taken according to the image as it was found, even if that's been
+    /// stripped from what's published. Only used for dating posts.
+    pub taken_at: Option<NaiveDateTime>,
+    /// For finding resized or recompressed copies, if the image could be decoded.
+    pub perceptual_hash: Option<u64>,
+    /// Problems found while storing the image, reported for each post it appears in.
+    pub warnings: Vec<String>,
+}
+
+/// The result of storing an image, filled in by the first worker to find it while any others
+/// wait for it.
+type StoreResult = Arc<OnceLock<Result<StoredImage, String>>>;
+
+/// Images written so far, shared between the workers generating pages.
+pub struct ImageStore {
+    dir: PathBuf,
+    /// Keyed by the stems of filenames, which are hashes of image contents.
+    images: Mutex<HashMap<String, StoreResult>>,
+    /// Number of images without WebP copies despite that being the output format, since
+    /// they have no transparency.
+    without_webp: AtomicUsize,
+}
+
+/// Start of the SHA-256 of `contents` in hex, which names files the same in every build so that
+/// links to images stay valid when a site is regenerated.
+fn content_stem(contents: &[u8]) -> String {
+    Sha256::digest(contents)[..8]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+impl ImageStore {
+    pub fn new(output_dir: &Path) -> Result<ImageStore, io::Error> {
+        let dir = output_dir.join(IMAGES_DIR);
+        fs::create_dir_all(&dir)?;
+        Ok(ImageStore {
+            dir,
+            images: Mutex::new(HashMap::new()),
+            without_webp: AtomicUsize::new(0),
+        })
+    }
+
+    /// Stores an image unless identical contents have already been stored, returning where it
+    /// and its thumbnails are.
+    pub fn add(
+        &self,
+        contents: &[u8],
+        content_type: &str,
+        options: &SiteOptions,
+    ) -> Result<StoredImage, io::Error> {
+        let stem = content_stem(contents);
+        let cell = Arc::clone(self.images.lock().unwrap().entry(stem.clone()).or_default());
+        cell.get_or_init(|| {
+            self.write(contents, content_type, &stem, options)
+                .map_err(|err| err.to_string())
+        })
+        .clone()
+        .map_err(io::Error::other)
+    }
+
+    /// Number of images stored so far which have no WebP copies though that's the output
+    /// format.
+    pub fn num_without_webp(&self) -> usize {
+        self.without_webp.load(Ordering::Relaxed)
+    }
+
+    fn write(
+        &self,
+        contents: &[u8],
+        content_type: &str,
+        stem: &str,
+        options: &SiteOptions,
+    ) -> Result<StoredImage, io::Error> {
+        let filename = format!("{stem}.{}", image_extension(content_type));
+        let in_images_dir = |file: &str| format!("{IMAGES_DIR}/{file}");
+        let original = in_images_dir(&filename);
+        let mut path = Some(original.clone());
+        let mut warnings: Vec<String> = Vec::new();
+        let decoded =
+            thumbnail::can_decode(content_type).then(|| thumbnail::decode_oriented(contents));
+        // Metadata is read from what's published, so stripped data doesn't reappear in
+        // posts.json or image pages.
+        let stripped = options
+            .strip_metadata
+            .map(|mode| strip_metadata::strip(contents, content_type, mode));
+        let published: Option<&[u8]> = match &stripped {
+            None => Some(contents),
+            Some(Some(stripped)) => Some(stripped),
+            // Rather than risk publishing the metadata, the image is re-encoded, which leaves
+            // all of it out, or not published at all.
+            Some(None) => match &decoded {
+                Some(Ok(image)) => {
+                    let file =
+                        thumbnail::save_copy(image, options.derivatives.quality, &self.dir, stem)
+                            .map_err(io::Error::other)?;
+                    warnings.push(format!(
+                        "couldn't strip metadata from {original}, so it was re-encoded as {file}"
+                    ));
+                    path = Some(in_images_dir(&file));
+                    None
+                }
+                _ => {
+                    warnings.push(format!(
+                        "couldn't strip metadata from {original}, so it was left out"
+                    ));
+                    path = None;
+                    None
+                }
+            },
+        };
+        if let Some(published) = published {
+            fs::write(self.dir.join(&filename), published)?;
+        }
+        let mut versions: Vec<SizedImage> = Vec::new();
+        let mut perceptual_hash: Option<u64> = None;
+        // Browsers can show formats we can't decode, such as SVG, so they're their own
+        // thumbnails.
+        let (thumbnail, thumbnail_alternative) = if let Some(decoded) = decoded {
+            let derivatives = decoded.and_then(|image| {
+                perceptual_hash = Some(duplicates::perceptual_hash(&image));
+                thumbnail::create_derivatives(&image, &self.dir, stem, &options.derivatives)
+            });
+            match derivatives {
+                Ok(derivatives) => {
+                    if options.derivatives.format == thumbnail::OutputFormat::Webp
+                        && derivatives.thumbnail.alternative.is_none()
+                    {
+                        self.without_webp.fetch_add(1, Ordering::Relaxed);
+                    }
+                    for (width, files) in derivatives.resized {
+                        versions.push(SizedImage {
+                            width,
+                            path: in_images_dir(&files.file),
+                            alternative: files.alternative.as_deref().map(in_images_dir),
+                        });
+                    }
+                    if let Some(path) = &path {
+                        versions.push(SizedImage {
+                            width: derivatives.width,
+                            path: path.clone(),
+                            alternative: None,
+                        });
+                    }
+                    (
+                        in_images_dir(&derivatives.thumbnail.file),
+                        derivatives
+                            .thumbnail
+                            .alternative
+                            .as_deref()
+                            .map(in_images_dir),
+                    )
+                }
+                Err(err) => {
+                    // Left out images have already been warned about.
+                    if path.is_some() {
+                        warnings.push(format!("couldn't make a thumbnail for {original}: {err}"));
+                    }
+                    (String::from(thumbnail::PLACEHOLDER_FILE), None)
+                }
+            }
+        } else {
+            let thumbnail = path.as_deref().unwrap_or(thumbnail::PLACEHOLDER_FILE);
+            (String::from(thumbnail), None)
+        };
+        Ok(StoredImage {
+            metadata: published
+                .map(|published| image_metadata::read(published, options.include_gps))
+                .unwrap_or_default(),
+            taken_at: image_metadata::read(contents, false).taken_at,
+            path,
+            thumbnail,
+            thumbnail_alternative,
+            versions,
+            perceptual_hash,
+            warnings,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strip_metadata::StripMetadata;
+    use crate::test_dir::TestDir;
+    use exif::experimental::Writer;
+    use exif::{Field, In, Rational, Tag, Value};
+    use image::DynamicImage;
+    use std::io::Cursor;
+
+    /// A PNG with `fields` in an eXIf chunk.
+    fn png_with_exif<const N: usize>(fields: [(Tag, Value); N]) -> Vec<u8> {
+        let mut contents: Vec<u8> = Vec::new();
+        DynamicImage::new_rgb8(8, 8)
+            .write_to(&mut Cursor::new(&mut contents), image::ImageFormat::Png)
+            .unwrap();
+        let fields = fields.map(|(tag, value)| Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value,
+        });
+        let mut writer = Writer::new();
+        for field in &fields {
+            writer.push_field(field);
+        }
+        let mut tiff = Cursor::new(Vec::new());
+        writer.write(&mut tiff, true).unwrap();
+        let tiff = tiff.into_inner();
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(b"eXIf");
+        hasher.update(&tiff);
+        let mut chunk = (tiff.len() as u32).to_be_bytes().to_vec();
+        chunk.extend(b"eXIf");
+        chunk.extend(&tiff);
+        chunk.extend(hasher.finalize().to_be_bytes());
+        // After the signature and the IHDR chunk.
+        contents.splice(33..33, chunk);
+        contents
+    }
+
+    /// A PNG with a GPS position in an eXIf chunk, followed by bytes which aren't a chunk.
+    fn unstrippable_png_with_gps() -> Vec<u8> {
+        let degrees = Value::Rational(vec![Rational { num: 45, denom: 1 }; 3]);
+        let mut contents = png_with_exif([
+            (Tag::GPSLatitudeRef, Value::Ascii(vec![b"N".to_vec()])),
+            (Tag::GPSLatitude, degrees.clone()),
+            (Tag::GPSLongitudeRef, Value::Ascii(vec![b"E".to_vec()])),
+            (Tag::GPSLongitude, degrees),
+        ]);
+        contents.extend(b"junk");
+        contents
+    }
+
+    #[test]
+    fn add_stores_identical_images_once() {
+        let output_dir = TestDir::new("add_stores_identical_images_once");
+        let store = ImageStore::new(output_dir.path()).unwrap();
+        let options: SiteOptions = Default::default();
+        let first = store.add(b"<svg/>", "image/svg+xml", &options).unwrap();
+        let second = store.add(b"<svg/>", "image/svg+xml", &options).unwrap();
+        let other = store
+            .add(b"<svg></svg>", "image/svg+xml", &options)
+            .unwrap();
+        assert_eq!(first.path, second.path);
+        assert_ne!(first.path, other.path);
+        assert_eq!(first.path.as_deref(), Some("images/d4dc56669143034f.svg"));
+        assert_eq!(
+            fs::read_dir(output_dir.path().join(IMAGES_DIR))
+                .unwrap()
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn add_repeats_warnings() {
+        let output_dir = TestDir::new("add_repeats_warnings");
+        let store = ImageStore::new(output_dir.path()).unwrap();
+        let options: SiteOptions = Default::default();
+        let contents = b"\xFF\xD8\xFF\xE0 not really a JPEG";
+        for _ in 0..2 {
+            let stored = store.add(contents, "image/jpeg", &options).unwrap();
+            assert_eq!(stored.thumbnail, thumbnail::PLACEHOLDER_FILE);
+            assert_eq!(stored.warnings.len(), 1);
+        }
+    }
+
+    #[test]
+    fn add_reencodes_images_it_cant_strip() {
+        let output_dir = TestDir::new("add_reencodes_images_it_cant_strip");
+        let store = ImageStore::new(output_dir.path()).unwrap();
+        let options = SiteOptions {
+            strip_metadata: Some(StripMetadata::Gps),
+            ..Default::default()
+        };
+        let contents = unstrippable_png_with_gps();
+        assert!(image_metadata::read(&contents, true).gps.is_some());
+        assert_eq!(
+            strip_metadata::strip(&contents, "image/png", StripMetadata::Gps),
+            None
+        );
+        let stored = store.add(&contents, "image/png", &options).unwrap();
+        assert_eq!(stored.warnings.len(), 1);
+        let written = fs::read(output_dir.path().join(stored.path.unwrap())).unwrap();
+        assert_eq!(image_metadata::read(&written, true).gps, None);
+        for entry in fs::read_dir(output_dir.path().join(IMAGES_DIR)).unwrap() {
+            let written = fs::read(entry.unwrap().path()).unwrap();
+            assert_eq!(image_metadata::read(&written, true).gps, None);
+        }
+    }
+
+    #[test]
+    fn add_keeps_taken_at_of_stripped_images() {
+        let output_dir = TestDir::new("add_keeps_taken_at_of_stripped_images");
+        let store = ImageStore::new(output_dir.path()).unwrap();
+        let options = SiteOptions {
+            strip_metadata: Some(StripMetadata::All),
+            ..Default::default()
+        };
+        let contents = png_with_exif([(
+            Tag::DateTimeOriginal,
+            Value::Ascii(vec![b"2023:07:19 14:30:00".to_vec()]),
+        )]);
+        let stored = store.add(&contents, "image/png", &options).unwrap();
+        assert!(stored.warnings.is_empty());
+        assert_eq!(stored.metadata, Default::default());
+        assert_eq!(
+            stored.taken_at.map(|taken_at| taken_at.to_string()),
+            Some(String::from("2023-07-19 14:30:00"))
+        );
+    }
+
+    #[test]
+    fn add_leaves_out_images_it_cant_strip_or_decode() {
+        let output_dir = TestDir::new("add_leaves_out_images_it_cant_strip_or_decode");
+        let store = ImageStore::new(output_dir.path()).unwrap();
+        let options = SiteOptions {
+            strip_metadata: Some(StripMetadata::Gps),
+            ..Default::default()
+        };
+        let contents = b"\xFF\xD8\xFF\xE0 not really a JPEG";
+        let stored = store.add(contents, "image/jpeg", &options).unwrap();
+        assert_eq!(stored.path, None);
+        assert_eq!(stored.warnings.len(), 1);
+        assert_eq!(stored.thumbnail, thumbnail::PLACEHOLDER_FILE);
+        assert!(stored.versions.is_empty());
+        assert_eq!(
+            fs::read_dir(output_dir.path().join(IMAGES_DIR))
+                .unwrap()
+                .count(),
+            0
+        );
+    }
+}
@@ -0,0 +1,67 @@
+// The site's front page, listing every post.
+
+use crate::{
+    authors, calendar, feeds, gallery, layout, missing_images, search, tags, Page, SiteOptions,
+};
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const INDEX_FILE: &str = "index.html";
+
+fn make_index_html(pages: &[Page], options: &SiteOptions) -> String {
+    let text = options.locale.catalog();
+    let mut links = vec![
+        format!(r#"<a href="{}">{}</a>"#, search::SEARCH_FILE, text.search),
+        format!(
+            r#"<a href="{}">{}</a>"#,
+            gallery::GALLERY_FILE,
+            text.gallery
+        ),
+        format!(
+            r#"<a href="{}">{}</a>"#,
+            calendar::CALENDAR_FILE,
+            text.calendar
+        ),
+        format!(
+            r#"<a href="{}">{}</a>"#,
+            authors::AUTHORS_INDEX_FILE,
+            text.authors
+        ),
+    ];
+    if options.tag_pages {
+        links.push(format!(
+            r#"<a href="{}">{}</a>"#,
+            tags::TAGS_INDEX_FILE,
+            text.tags
+        ));
+    }
+    if missing_images::any_missing(pages) {
+        links.push(format!(
+            r#"<a href="{}">{}</a>"#,
+            missing_images::MISSING_IMAGES_FILE,
+            text.missing_images
+        ));
+    }
+    links.push(format!(
+        r#"<a href="{}">{}</a>"#,
+        feeds::ATOM_FILE,
+        text.feed
+    ));
+    let page_refs: Vec<&Page> = pages.iter().collect();
+    let body = format!(
+        "<nav>{links}</nav>\n        {posts}",
+        links = links.join(" | "),
+        posts = layout::render_post_list(&page_refs, options),
+    );
+    layout::render_page(&options.site_title, &body, options)
+}
+
+pub fn write_index_page(
+    pages: &[Page],
+    output_dir: &Path,
+    options: &SiteOptions,
+) -> Result<(), io::Error> {
+    fs::write(output_dir.join(INDEX_FILE), make_index_html(pages, options))
+}
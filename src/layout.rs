@@ -0,0 +1,163 @@
+// Shared markup for the site-level pages (indexes, listings) generated alongside the posts.
+
+use crate::locale::Catalog;
+use crate::{feeds, Page, SiteOptions};
+
+use chrono::NaiveDate;
+
+use htmlize::{escape_attribute, escape_text};
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const STYLE_FILE: &str = "style.css";
+pub const EXTRA_CSS_FILE: &str = "extra.css";
+
+/// The theme used unless replaced with --theme.
+const DEFAULT_THEME_CSS: &str = include_str!("../assets/theme.css");
+
+/// Tags for the head of every page: the viewport setting needed for the theme to adapt to
+/// phones, the thumbnail height for the theme to show thumbnails at, and links to the
+/// stylesheets.
+pub fn head_html(options: &SiteOptions) -> String {
+    let mut html = format!(
+        r#"<meta name="viewport" content="width=device-width, initial-scale=1">
+    <style>:root {{ --thumbnail-height: {height}px; }}</style>
+    <link rel="stylesheet" href="{STYLE_FILE}">"#,
+        height = options.derivatives.thumbnail_height,
+    );
+    if options.extra_css.is_some() {
+        html.push_str(&format!(
+            r#"
+    <link rel="stylesheet" href="{EXTRA_CSS_FILE}">"#
+        ));
+    }
+    html
+}
+
+/// Writes the theme (or its replacement) and any extra CSS into the output dir.
+pub fn write_stylesheets(output_dir: &Path, options: &SiteOptions) -> Result<(), io::Error> {
+    fs::write(
+        output_dir.join(STYLE_FILE),
+        options.theme_css.as_deref().unwrap_or(DEFAULT_THEME_CSS),
+    )?;
+    if let Some(extra_css) = &options.extra_css {
+        fs::write(output_dir.join(EXTRA_CSS_FILE), extra_css)?;
+    }
+    Ok(())
+}
+
+/// Link for keyboard users to jump past the header to the page's main landmark.
+pub fn skip_link_html(text: &Catalog) -> String {
+    format!(
+        r##"<a class="skip-link" href="#main">{}</a>"##,
+        text.skip_to_content
+    )
+}
+
+/// Renders `date` for display, marked up with its machine-readable form.
+pub fn render_date(date: NaiveDate, options: &SiteOptions) -> String {
+    format!(
+        r#"<time datetime="{}">{}</time>"#,
+        date.format("%Y-%m-%d"),
+        options.format_date(date)
+    )
+}
+
+/// Wraps `body_html` in a complete HTML document.
+pub fn render_page(title: &str, body_html: &str, options: &SiteOptions) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang='{lang}'>
+    <head>
+        <title>{title}</title>
+    <meta charset='utf-8'>
+    {head}
+    </head>
+    <body>
+        {skip_link}
+        <main id="main">
+          <h1>{title}</h1>
+          {body_html}
+        </main>
+    </body>
+</html>"#,
+        lang = options.locale.catalog().lang,
+        title = escape_text(title),
+        head = head_html(options),
+        skip_link = skip_link_html(options.locale.catalog()),
+    )
+}
+
+/// Wraps `img_html` in a picture element offering `alternative`, a copy of the same image in a
+/// format some browsers can't show, if there is one.
+pub fn picture_html(img_html: &str, alternative: Option<&str>) -> String {
+    match alternative {
+        Some(alternative) => format!(
+            r#"<picture><source srcset="{srcset}" type="{mime_type}">{img_html}</picture>"#,
+            srcset = escape_attribute(alternative),
+            mime_type = feeds::image_mime_type(alternative),
+        ),
+        None => String::from(img_html),
+    }
+}
+
+/// Renders a list of posts, each with its title, date and thumbnails.
+pub fn render_post_list(pages: &[&Page], options: &SiteOptions) -> String {
+    let mut html = String::from("<ul>\n");
+    for page in pages {
+        html.push_str(&format!(
+            r#"<li><a href="{href}">{title}</a> ({date})"#,
+            href = escape_attribute(&page.output_file),
+            title = escape_text(&page.title),
+            date = render_date(page.post_date, options),
+        ));
+        if !page.thumbnails.is_empty() {
+            html.push_str("<br>");
+            for (i, thumbnail) in page.thumbnails.iter().enumerate() {
+                let alt = page
+                    .image_captions
+                    .get(i)
+                    .cloned()
+                    .flatten()
+                    .unwrap_or_else(|| page.title.clone());
+                let img_html = format!(
+                    r#"<img src="{src}" alt="{alt}">"#,
+                    src = escape_attribute(thumbnail),
+                    alt = escape_attribute(&alt),
+                );
+                let alternative = page.thumbnail_alternatives.get(i).cloned().flatten();
+                html.push_str(&format!(
+                    r#"<a href="{href}">{picture}</a>"#,
+                    href = escape_attribute(&page.output_file),
+                    picture = picture_html(&img_html, alternative.as_deref()),
+                ));
+            }
+        }
+        html.push_str("</li>\n");
+    }
+    html.push_str("</ul>");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extra_css_linked_after_theme() {
+        let mut options: SiteOptions = Default::default();
+        assert!(!head_html(&options).contains(EXTRA_CSS_FILE));
+        options.extra_css = Some(String::from("body { color: red }"));
+        let html = head_html(&options);
+        assert!(html.find(STYLE_FILE).unwrap() < html.find(EXTRA_CSS_FILE).unwrap());
+    }
+
+    #[test]
+    fn head_sets_thumbnail_height() {
+        let mut options: SiteOptions = Default::default();
+        options.derivatives.thumbnail_height = 200;
+        assert!(head_html(&options).contains("--thumbnail-height: 200px;"));
+    }
+}
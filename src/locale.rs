@@ -0,0 +1,287 @@
+// Translations of the text in generated pages, and locale-aware date formatting.
+
+use chrono::{NaiveDate, NaiveDateTime};
+
+/// Languages which generated pages can be written in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Locale {
+    #[default]
+    En,
+    Fr,
+}
+
+impl Locale {
+    pub fn catalog(self) -> &'static Catalog {
+        match self {
+            Locale::En => &EN,
+            Locale::Fr => &FR,
+        }
+    }
+}
+
+/// The UI text for one language. Templates contain `{name}` placeholders, which `fill` replaces.
+pub struct Catalog {
+    /// Value for the `lang` attribute of generated pages.
+    pub lang: &'static str,
+    chrono_locale: chrono::Locale,
+    /// Format for dates such as post dates.
+    pub date_format: &'static str,
+    /// Format for dates with a time of day, such as scrape dates.
+    pub datetime_format: &'static str,
+    /// Format for a month and day without a year, as in the "on this day" headings.
+    month_day_format: &'static str,
+    /// Column headings for calendar months, starting on Monday.
+    pub weekday_labels: [&'static str; 7],
+    /// Labels for the EXIF summary on image pages.
+    pub exif_labels: ExifLabels,
+    /// Template with `{date}` and `{url}`.
+    pub scraped_from: &'static str,
+    pub search: &'static str,
+    pub gallery: &'static str,
+    pub calendar: &'static str,
+    pub authors: &'static str,
+    pub tags: &'static str,
+    pub feed: &'static str,
+    pub all_posts: &'static str,
+    pub all_authors: &'static str,
+    pub all_tags: &'static str,
+    pub related_posts: &'static str,
+    /// Template with `{title}`.
+    pub next_post: &'static str,
+    /// Template with `{title}`.
+    pub previous_post: &'static str,
+    pub next: &'static str,
+    pub previous: &'static str,
+    pub newer: &'static str,
+    pub older: &'static str,
+    pub on_this_day: &'static str,
+    pub back_to_post: &'static str,
+    /// Template with `{number}` and `{total}`.
+    pub photo_number: &'static str,
+    /// Template with `{count}`, for counts which take the plural.
+    pub post_count: &'static str,
+    /// Template with `{count}`, for counts which take the singular.
+    pub post_count_one: &'static str,
+    /// Whether a count of zero takes the singular, as in French.
+    pub zero_is_singular: bool,
+    pub skip_to_content: &'static str,
+    pub photo_viewer: &'static str,
+    pub close: &'static str,
+    pub previous_photo: &'static str,
+    pub next_photo: &'static str,
+    pub duplicate_photos: &'static str,
+    /// Template with `{count}`.
+    pub duplicate_count: &'static str,
+    /// Precedes links to other posts containing the same photo.
+    pub also_in: &'static str,
+    /// Alt text for the placeholder shown instead of an image which wasn't saved.
+    pub missing_image: &'static str,
+    pub missing_images: &'static str,
+    /// Template with `{count}`.
+    pub missing_image_count: &'static str,
+}
+
+pub struct ExifLabels {
+    pub taken_at: &'static str,
+    pub camera: &'static str,
+    pub lens: &'static str,
+    pub focal_length: &'static str,
+    pub aperture: &'static str,
+    pub exposure: &'static str,
+    pub iso: &'static str,
+    pub location: &'static str,
+}
+
+impl Catalog {
+    /// Formats `datetime` with `format`, a strftime string, using this locale's names for months
+    /// and days.
+    pub fn localize(&self, datetime: NaiveDateTime, format: &str) -> String {
+        // Only DateTime supports localized formatting. Its offset doesn't matter, since display
+        // formats don't include one.
+        datetime
+            .and_utc()
+            .format_localized(format, self.chrono_locale)
+            .to_string()
+    }
+
+    pub fn format_month_day(&self, date: NaiveDate) -> String {
+        date.format_localized(self.month_day_format, self.chrono_locale)
+            .to_string()
+    }
+
+    /// Full name of the month of `date`.
+    pub fn format_month(&self, date: NaiveDate) -> String {
+        date.format_localized("%B", self.chrono_locale).to_string()
+    }
+
+    /// The template for describing `count` posts, singular or plural as the count needs.
+    pub fn post_count_template(&self, count: usize) -> &'static str {
+        if count == 1 || (count == 0 && self.zero_is_singular) {
+            self.post_count_one
+        } else {
+            self.post_count
+        }
+    }
+
+    pub fn format_post_count(&self, count: usize) -> String {
+        fill(
+            self.post_count_template(count),
+            &[("count", &count.to_string())],
+        )
+    }
+}
+
+/// Replaces each `{name}` in `template` with its value. Values are inserted as is, so HTML
+/// callers must escape them first.
+pub fn fill(template: &str, args: &[(&str, &str)]) -> String {
+    let mut result = String::from(template);
+    for (name, value) in args {
+        result = result.replace(&format!("{{{name}}}"), value);
+    }
+    result
+}
+
+static EN: Catalog = Catalog {
+    lang: "en",
+    chrono_locale: chrono::Locale::en_US,
+    date_format: "%b %d, %Y",
+    datetime_format: "%b %d, %Y, %-I:%M %p",
+    month_day_format: "%B %-d",
+    weekday_labels: ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"],
+    exif_labels: ExifLabels {
+        taken_at: "Taken",
+        camera: "Camera",
+        lens: "Lens",
+        focal_length: "Focal length",
+        aperture: "Aperture",
+        exposure: "Exposure",
+        iso: "ISO",
+        location: "Location",
+    },
+    scraped_from: "Scraped on {date} from {url}",
+    search: "Search",
+    gallery: "Gallery",
+    calendar: "Calendar",
+    authors: "Authors",
+    tags: "Tags",
+    feed: "Feed",
+    all_posts: "All posts",
+    all_authors: "All authors",
+    all_tags: "All tags",
+    related_posts: "Related posts",
+    next_post: "Next: {title}",
+    previous_post: "Previous: {title}",
+    next: "Next",
+    previous: "Previous",
+    newer: "Newer",
+    older: "Older",
+    on_this_day: "On this day",
+    back_to_post: "Back to post",
+    photo_number: "Photo {number} of {total}",
+    post_count: "{count} posts",
+    post_count_one: "{count} post",
+    zero_is_singular: false,
+    skip_to_content: "Skip to content",
+    photo_viewer: "Photo viewer",
+    close: "Close",
+    previous_photo: "Previous photo",
+    next_photo: "Next photo",
+    duplicate_photos: "Duplicate photos",
+    duplicate_count: "Photos in more than one post: {count}",
+    also_in: "Also in:",
+    missing_image: "Image not available",
+    missing_images: "Missing images",
+    missing_image_count: "Posts with images missing from their saved page: {count}",
+};
+
+static FR: Catalog = Catalog {
+    lang: "fr",
+    chrono_locale: chrono::Locale::fr_FR,
+    date_format: "%-d %b %Y",
+    datetime_format: "%-d %b %Y à %H:%M",
+    month_day_format: "%-d %B",
+    weekday_labels: ["Lu", "Ma", "Me", "Je", "Ve", "Sa", "Di"],
+    exif_labels: ExifLabels {
+        taken_at: "Prise le",
+        camera: "Appareil",
+        lens: "Objectif",
+        focal_length: "Focale",
+        aperture: "Ouverture",
+        exposure: "Exposition",
+        iso: "ISO",
+        location: "Lieu",
+    },
+    scraped_from: "Récupéré le {date} depuis {url}",
+    search: "Recherche",
+    gallery: "Galerie",
+    calendar: "Calendrier",
+    authors: "Auteurs",
+    tags: "Mots-clés",
+    feed: "Flux",
+    all_posts: "Toutes les publications",
+    all_authors: "Tous les auteurs",
+    all_tags: "Tous les mots-clés",
+    related_posts: "Publications similaires",
+    next_post: "Suivante : {title}",
+    previous_post: "Précédente : {title}",
+    next: "Suivante",
+    previous: "Précédente",
+    newer: "Plus récentes",
+    older: "Plus anciennes",
+    on_this_day: "Ce jour-là",
+    back_to_post: "Retour à la publication",
+    photo_number: "Photo {number} sur {total}",
+    post_count: "{count} publications",
+    post_count_one: "{count} publication",
+    zero_is_singular: true,
+    skip_to_content: "Aller au contenu",
+    photo_viewer: "Visionneuse de photos",
+    close: "Fermer",
+    previous_photo: "Photo précédente",
+    next_photo: "Photo suivante",
+    duplicate_photos: "Photos en double",
+    duplicate_count: "Photos présentes dans plusieurs publications : {count}",
+    also_in: "Aussi dans :",
+    missing_image: "Image non disponible",
+    missing_images: "Images manquantes",
+    missing_image_count: "Publications dont des images manquent dans la page enregistrée : {count}",
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fill_replaces_placeholders() {
+        assert_eq!(
+            fill(EN.photo_number, &[("number", "2"), ("total", "5")]),
+            "Photo 2 of 5"
+        );
+    }
+
+    #[test]
+    fn post_count_agrees_with_count() {
+        assert_eq!(EN.format_post_count(0), "0 posts");
+        assert_eq!(EN.format_post_count(1), "1 post");
+        assert_eq!(EN.format_post_count(2), "2 posts");
+        assert_eq!(FR.format_post_count(0), "0 publication");
+        assert_eq!(FR.format_post_count(1), "1 publication");
+        assert_eq!(FR.format_post_count(2), "2 publications");
+    }
+
+    #[test]
+    fn dates_use_locale_month_names() {
+        let date = NaiveDate::from_ymd_opt(2023, 7, 9).unwrap();
+        let datetime = date.and_hms_opt(14, 2, 0).unwrap();
+        assert_eq!(
+            EN.localize(datetime, EN.datetime_format),
+            "Jul 09, 2023, 2:02 PM"
+        );
+        assert_eq!(FR.localize(datetime, FR.date_format), "9 juil. 2023");
+        assert_eq!(
+            FR.localize(datetime, FR.datetime_format),
+            "9 juil. 2023 à 14:02"
+        );
+        assert_eq!(Locale::Fr.catalog().format_month_day(date), "9 juillet");
+    }
+}
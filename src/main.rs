@@ -2,11 +2,40 @@
 //
 // This code focuses on the case where the posts are focused on displaying photos.
 
+// Style lints which the original parsing code trips; left as written rather than reworked.
+#![allow(
+    clippy::field_reassign_with_default,
+    clippy::ptr_arg,
+    clippy::unnecessary_mut_passed
+)]
+
+mod accessibility;
+mod authors;
+mod calendar;
+mod duplicates;
+mod feeds;
+mod gallery;
+mod image_metadata;
+mod image_store;
+mod index;
+mod layout;
+mod locale;
+mod metadata;
 pub mod mhtml;
+mod missing_images;
+mod navigation;
+mod photos;
+mod search;
+mod sitemap;
+mod strip_metadata;
+mod tags;
+#[cfg(test)]
+mod test_dir;
 pub mod thumbnail;
 pub mod utf8_bytes;
 
-use chrono::{DateTime, FixedOffset, NaiveDate};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
 use clap::Parser;
 // Using feature "unescape"
 
@@ -22,13 +51,24 @@ use std::collections::HashSet;
 use std::fs;
 use std::hash::{DefaultHasher, Hash, Hasher};
 use std::io;
-use std::sync::OnceLock;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
 use std::vec::Vec;
 
 const INITIAL_TEXT_MAX_LEN: usize = 140;
+/// Version of the format of posts.json. Bump when fields are removed or change meaning.
+const POSTS_SCHEMA_VERSION: u32 = 3;
 const MIN_I_TEXT_LEN: usize = 3;
 const MAX_I_TEXT_LEN: usize = 50;
 
+/// Checks that `format` is a valid strftime format, since formatting with an invalid one panics.
+fn parse_strftime_format(format: &str) -> Result<String, String> {
+    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+        return Err(format!("invalid strftime format \"{format}\""));
+    }
+    Ok(String::from(format))
+}
+
 /// Generate a site from a directory of Google Group MHTML files.
 #[derive(Parser)]
 #[command(rename_all = "snake_case")]
@@ -40,13 +80,218 @@ struct Cli {
     /// Path to the directory for output files.
     #[arg(short, long, value_name = "DIR")]
     output_dir: std::path::PathBuf,
+
+    /// Path to a JSON file mapping canonical author names to their other spellings.
+    #[arg(long, value_name = "FILE")]
+    author_aliases: Option<std::path::PathBuf>,
+
+    /// Generate a page for each phrase in italics, plus a tag cloud.
+    #[arg(long)]
+    tag_pages: bool,
+
+    /// Path to a JSON file with "synonyms" and "stopwords" for cleaning up tags.
+    #[arg(long, value_name = "FILE", requires = "tag_pages")]
+    tag_config: Option<std::path::PathBuf>,
+
+    /// URL at which the site will be published, used for absolute links. Required for sitemap.xml.
+    #[arg(long, value_name = "URL")]
+    base_url: Option<String>,
+
+    /// Path to a file to publish as robots.txt instead of the default, which allows everything.
+    #[arg(long, value_name = "FILE")]
+    robots_txt: Option<std::path::PathBuf>,
+
+    /// Generate a page for each photo, with its caption and EXIF summary.
+    #[arg(long)]
+    image_pages: bool,
+
+    /// Add a viewer to post pages for stepping through photos with the keyboard.
+    #[arg(long)]
+    lightbox: bool,
+
+    /// Include where photos were taken, from their EXIF GPS data, in posts.json and image pages.
+    #[arg(long)]
+    include_gps: bool,
+
+    /// When a post has no date of its own, use the earliest date a photo in it was taken
+    /// rather than the scrape date.
+    #[arg(long)]
+    exif_post_date: bool,
+
+    /// Height in pixels of thumbnails.
+    #[arg(long, value_name = "PX", default_value_t = thumbnail::DerivativeOptions::default().thumbnail_height)]
+    thumbnail_height: u32,
+
+    /// Widths in pixels of resized copies of each image, such as "800,1600", for browsers on
+    /// smaller screens to download instead of the original.
+    #[arg(long, value_name = "PX,...", value_delimiter = ',')]
+    image_widths: Vec<u32>,
+
+    /// Format for thumbnails and resized copies. With WebP or AVIF, JPEG copies are kept as
+    /// fallbacks for browsers without support. WebP copies are lossless, so they're only made
+    /// of images with transparency, instead of larger PNGs.
+    #[arg(long, value_enum, default_value_t)]
+    image_format: thumbnail::OutputFormat,
+
+    /// Quality from 1 to 100 for JPEG and AVIF thumbnails and resized copies.
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100),
+        default_value_t = thumbnail::DerivativeOptions::default().quality)]
+    image_quality: u8,
+
+    /// Write duplicates.html, listing photos which appear in more than one post, even if
+    /// resized or recompressed.
+    #[arg(long)]
+    duplicates_report: bool,
+
+    /// Link each gallery photo to the other posts it appears in.
+    #[arg(long)]
+    mark_duplicates: bool,
+
+    /// Directory of previously downloaded images to use for any missing from the MHTML, named
+    /// by their percent-encoded URLs, such as "https%3A%2F%2Flh3.googleusercontent.com%2Fabc".
+    #[arg(long, value_name = "DIR")]
+    image_cache: Option<std::path::PathBuf>,
+
+    /// Show a placeholder for images which are missing from the MHTML (and the image cache)
+    /// instead of linking to the original URL.
+    #[arg(long)]
+    placeholder_missing_images: bool,
+
+    /// Remove metadata from published JPEG and PNG images, either all of it or just GPS
+    /// positions, without re-encoding them. Other images, and any it fails for, are re-encoded
+    /// if they can be decoded and otherwise left out.
+    #[arg(long, value_enum, conflicts_with = "include_gps")]
+    strip_metadata: Option<strip_metadata::StripMetadata>,
+
+    /// Title for the site as a whole.
+    #[arg(long, default_value = "Posts")]
+    site_title: String,
+
+    /// Language for the text and dates in generated pages.
+    #[arg(long, value_enum, default_value_t)]
+    locale: locale::Locale,
+
+    /// Timezone for displaying dates, such as "Europe/Paris". Defaults to the offset recorded
+    /// when each post was scraped.
+    #[arg(long, value_name = "TZ")]
+    timezone: Option<chrono_tz::Tz>,
+
+    /// strftime format for displaying dates, instead of the locale's.
+    #[arg(long, value_name = "FORMAT", value_parser = parse_strftime_format)]
+    date_format: Option<String>,
+
+    /// strftime format for displaying dates with times, instead of the locale's.
+    #[arg(long, value_name = "FORMAT", value_parser = parse_strftime_format)]
+    datetime_format: Option<String>,
+
+    /// Path to a CSS file to use instead of the default theme.
+    #[arg(long, value_name = "FILE")]
+    theme: Option<std::path::PathBuf>,
+
+    /// Path to a CSS file to load after the theme, for small adjustments.
+    #[arg(long, value_name = "FILE")]
+    extra_css: Option<std::path::PathBuf>,
+}
+
+/// Settings shared by all of the pages in a site.
+#[derive(Default)]
+struct SiteOptions {
+    /// Maps normalized alternate spellings of author names to the canonical name.
+    author_aliases: HashMap<String, String>,
+    /// Whether to generate tag pages from i_text.
+    tag_pages: bool,
+    tag_config: tags::TagConfig,
+    /// URL at which the site will be published, if known.
+    base_url: Option<String>,
+    site_title: String,
+    /// Contents of robots.txt, if not the default.
+    robots_txt: Option<String>,
+    /// Whether to generate a page per image.
+    image_pages: bool,
+    /// Whether to include the photo viewer on post pages.
+    lightbox: bool,
+    /// Whether to read GPS positions from images.
+    include_gps: bool,
+    /// Whether to date posts by their photos before falling back to the scrape date.
+    exif_post_date: bool,
+    derivatives: thumbnail::DerivativeOptions,
+    /// Whether to write a report of photos appearing in more than one post.
+    duplicates_report: bool,
+    /// Whether to link gallery photos to other posts they appear in.
+    mark_duplicates: bool,
+    /// Directory of downloaded images to fill in for those missing from the MHTML, if any.
+    image_cache: Option<std::path::PathBuf>,
+    /// Whether to show a placeholder for images which are still missing.
+    placeholder_missing_images: bool,
+    /// What to remove from published images, if anything.
+    strip_metadata: Option<strip_metadata::StripMetadata>,
+    locale: locale::Locale,
+    /// Timezone for displaying dates, if not the one recorded when scraping.
+    timezone: Option<chrono_tz::Tz>,
+    /// Overrides for the locale's date formats.
+    date_format: Option<String>,
+    datetime_format: Option<String>,
+    /// Contents of style.css, if not the default theme.
+    theme_css: Option<String>,
+    /// Contents of extra.css, if any.
+    extra_css: Option<String>,
+}
+
+impl SiteOptions {
+    /// Returns the URL for `path` within the output dir, absolute if base_url is known.
+    fn url_for(&self, path: &str) -> String {
+        match &self.base_url {
+            Some(base_url) => format!("{}/{}", base_url.trim_end_matches('/'), path),
+            None => String::from(path),
+        }
+    }
+
+    /// Converts `datetime` to the display timezone, or keeps its own offset if none was set.
+    fn display_datetime(&self, datetime: DateTime<FixedOffset>) -> NaiveDateTime {
+        match self.timezone {
+            Some(timezone) => datetime.with_timezone(&timezone).naive_local(),
+            None => datetime.naive_local(),
+        }
+    }
+
+    /// When `page` was posted, if its time of day is known.
+    fn post_datetime(&self, page: &Page) -> Option<DateTime<FixedOffset>> {
+        let local = page.post_date.and_time(page.post_time?);
+        match self.timezone {
+            Some(timezone) => timezone
+                .from_local_datetime(&local)
+                .earliest()
+                .map(|datetime| datetime.fixed_offset()),
+            None => page
+                .scrape_date
+                .offset()
+                .from_local_datetime(&local)
+                .single(),
+        }
+    }
+
+    fn format_date(&self, date: NaiveDate) -> String {
+        let text = self.locale.catalog();
+        let format = self.date_format.as_deref().unwrap_or(text.date_format);
+        text.localize(date.and_time(NaiveTime::MIN), format)
+    }
+
+    /// Formats a date and time which is already in the display timezone.
+    fn format_datetime(&self, datetime: NaiveDateTime) -> String {
+        let text = self.locale.catalog();
+        let format = self
+            .datetime_format
+            .as_deref()
+            .unwrap_or(text.datetime_format);
+        text.localize(datetime, format)
+    }
 }
 
 #[derive(Default)]
 struct GroupsPost {
     author: Option<String>,
-    /// Date extracted from the post.
-    date: Option<NaiveDate>,
+    /// Date and time extracted from the post, in the timezone of the browser which saved it.
+    date: Option<NaiveDateTime>,
     /// HTML fragment for the main post.
     html: String,
     /// URLs of images used within post_html.
@@ -58,22 +303,47 @@ struct GroupsPost {
 #[derive(Default, Serialize)]
 struct Page {
     title: String,
+    /// Canonical name of the author, if known.
+    author: Option<String>,
     /// Date on which the content was scraped.
     scrape_date: DateTime<FixedOffset>,
     /// Best guess as to when it was originally posted.
     post_date: NaiveDate,
+    /// Time of day at which it was posted, if known, in the same timezone as post_date.
+    post_time: Option<NaiveTime>,
     /// Original URL at which the post appeared.
     original_url: String,
     /// Name within output dir.
     output_file: String,
-    /// Name within output dir.
-    images_dir: String,
     /// A segment of text from the beginning of the post, stripped of HTML.
     initial_text: String,
-    /// Paths to thumbnails for images within images_dir.
+    /// Paths to images within output dir, in order of appearance. Posts containing identical
+    /// images share the same file.
+    images: Vec<String>,
+    /// Paths to thumbnails for images within output dir, parallel to images.
     thumbnails: Vec<String>,
+    /// Paths to thumbnails in WebP or AVIF format, if made, parallel to images.
+    thumbnail_alternatives: Vec<Option<String>>,
+    /// Versions of each image by width, narrowest first and ending with the image itself,
+    /// parallel to images. Empty for images which couldn't be decoded.
+    image_versions: Vec<Vec<thumbnail::SizedImage>>,
+    /// EXIF metadata for each image, parallel to images.
+    image_metadata: Vec<image_metadata::ImageMetadata>,
+    /// Perceptual hash of each image which could be decoded, parallel to images.
+    #[serde(skip)]
+    image_hashes: Vec<Option<u64>>,
+    /// Caption for each image from nearby italic text, parallel to images.
+    image_captions: Vec<Option<String>>,
     /// Text from i tags, in order of first unique appearance.
     i_text: Vec<String>,
+    /// URLs of images in the post which weren't in the MHTML or the image cache.
+    missing_images: Vec<String>,
+    /// The post's HTML with images pointing to local copies, kept until the page is written.
+    #[serde(skip)]
+    post_html: String,
+    /// Problems found while generating the page, reported at the end of the build.
+    #[serde(skip)]
+    warnings: Vec<String>,
 }
 
 fn calculate_hash<T: Hash>(t: &T) -> u64 {
@@ -82,6 +352,39 @@ fn calculate_hash<T: Hash>(t: &T) -> u64 {
     s.finish()
 }
 
+/// Makes a filesystem-friendly name from `text`, made unique by a hash of `key`.
+fn make_basename(text: &str, key: &str) -> String {
+    let mut flattened = text.replace("/", "_").replace(" ", "_");
+    flattened.retain(|c| c.is_ascii_alphanumeric() || c == '_');
+    flattened.make_ascii_lowercase();
+    format!("{}_{:x}", flattened, calculate_hash(&key))
+}
+
+/// Lowercases `text` and collapses runs of whitespace, for case-insensitive lookups.
+fn normalize_key(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// File extension for an image with the given MIME type.
+fn image_extension(content_type: &str) -> String {
+    let subtype = content_type.strip_prefix("image/").unwrap_or(content_type);
+    match subtype {
+        "svg+xml" => String::from("svg"),
+        "x-icon" | "vnd.microsoft.icon" => String::from("ico"),
+        "jpg" | "pjpeg" => String::from("jpeg"),
+        "x-ms-bmp" => String::from("bmp"),
+        _ => subtype
+            .trim_start_matches("x-")
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase(),
+    }
+}
+
 fn invalid_data_err(message: &str) -> io::Error {
     io::Error::new(io::ErrorKind::InvalidData, message)
 }
@@ -98,7 +401,7 @@ fn date_from_title(title: &[u8]) -> Option<NaiveDate> {
     NaiveDate::from_ymd_opt(full_year, month, day)
 }
 
-fn get_text_from_html(html: &String) -> String {
+fn get_text_from_html(html: &str) -> String {
     static HTML_RE_LOCK: OnceLock<regex::Regex> = OnceLock::new();
     static MULTI_SPACE_RE_LOCK: OnceLock<regex::Regex> = OnceLock::new();
     static SPACE_PUNCTUATION_RE_LOCK: OnceLock<regex::Regex> = OnceLock::new();
@@ -115,17 +418,25 @@ fn get_text_from_html(html: &String) -> String {
     htmlize::unescape(despaced).trim().to_string()
 }
 
-fn date_from_html(html: &[u8]) -> Option<NaiveDate> {
+fn date_from_html(html: &[u8]) -> Option<NaiveDateTime> {
     static DATETIME_RE_LOCK: OnceLock<Regex> = OnceLock::new();
     let datetime_re = DATETIME_RE_LOCK.get_or_init(|| {
-        Regex::new(r#"<span[^>]*>\s*(?P<date>[A-Z][a-z]{2} \d+, \d{4}, \d{1,2}:\d\d:\d\d[^<]+(?:AM|PM))</span>"#)
+        Regex::new(r#"<span[^>]*>\s*(?P<date>[A-Z][a-z]{2} \d+, \d{4}), (?P<hour>\d{1,2}):(?P<minute>\d\d):(?P<second>\d\d)[^<]+?(?P<meridiem>AM|PM)</span>"#)
             .unwrap()
     });
     let captures = datetime_re.captures(html)?;
-    // u202F = NARROW NO-BREAK SPACE
-    let datetime_str = utf8_bytes::to_string(&captures["date"]);
-    let (date, _remainder) = NaiveDate::parse_and_remainder(&datetime_str, "%b %d, %Y").unwrap();
-    Some(date)
+    let date_str = utf8_bytes::to_string(&captures["date"]);
+    let date = NaiveDate::parse_from_str(&date_str, "%b %d, %Y").ok()?;
+    // The separator before AM/PM is typically u202F = NARROW NO-BREAK SPACE, so the parts are
+    // taken separately rather than parsed with a single format string.
+    let hour: u32 = utf8_bytes::to_str(&captures["hour"]).parse().unwrap();
+    let minute: u32 = utf8_bytes::to_str(&captures["minute"]).parse().unwrap();
+    let second: u32 = utf8_bytes::to_str(&captures["second"]).parse().unwrap();
+    let hour = match &captures["meridiem"] {
+        b"AM" => hour % 12,
+        _ => hour % 12 + 12,
+    };
+    date.and_hms_opt(hour, minute, second)
 }
 
 fn rewrite_i_tags(html: &String, i_texts: &mut Vec<String>) -> String {
@@ -194,11 +505,7 @@ fn parse_post_from_mhtml_piece(piece: &mhtml::MhtmlPiece) -> Result<GroupsPost,
     parse_groups_post(&piece.bytes)
 }
 
-fn make_output_html_for_post(
-    post: &GroupsPost,
-    page: &Page,
-    image_to_path: &HashMap<String, String>,
-) -> String {
+fn rewrite_post_html(post: &GroupsPost, image_to_path: &HashMap<String, String>) -> String {
     let mut img_count = 0;
     let element_content_handlers = vec![
         // Rewrite image links to point to local copies if available.
@@ -212,13 +519,13 @@ fn make_output_html_for_post(
 
             Ok(())
         }),
-        // Strip attributes other than href and src.
+        // Strip attributes other than href and src, keeping ids and alt text on images.
         element!("*", |el| {
             let attribute_names: Vec<String> = el.attributes().iter().map(|x| x.name()).collect();
             for attribute in attribute_names {
                 if attribute != "href"
                     && attribute != "src"
-                    && !(el.tag_name() == "img" && attribute == "id")
+                    && !(el.tag_name() == "img" && (attribute == "id" || attribute == "alt"))
                 {
                     el.remove_attribute(attribute.as_str());
                 }
@@ -235,33 +542,84 @@ fn make_output_html_for_post(
         },
     )
     .unwrap();
+    output_post_html
+}
+
+fn make_output_html_for_post(page: &Page, navigation_html: &str, options: &SiteOptions) -> String {
+    let text = options.locale.catalog();
     let mut info_pieces: Vec<String> = Vec::new();
-    if let Some(author) = &post.author {
+    if let Some(author) = &page.author {
         info_pieces.push(author.clone());
     }
-    info_pieces.push(page.post_date.format("%b %d, %Y").to_string());
+    info_pieces.push(match page.post_time {
+        Some(time) => format!(
+            r#"<time datetime="{}">{}</time>"#,
+            page.post_date.and_time(time).format("%Y-%m-%dT%H:%M:%S"),
+            options.format_datetime(page.post_date.and_time(time))
+        ),
+        None => layout::render_date(page.post_date, options),
+    });
 
     format!(
         r#"<!DOCTYPE html>
-<html lang='en'>
+<html lang='{lang}'>
     <head>
         <title>{title}</title>
     <meta charset='utf-8'>
+    {head}
+    {metadata}
     </head>
     <body>
-        <h1>{title}</h1>
-        <p>{info}</p>
-        {post_html}
-        <p>
-          <i>Scraped on {scrape_date} from <a href="{original_url}">{original_url}</a></i>
-        </p>
+        {skip_link}
+        <main id="main">
+          <article>
+            <h1>{title}</h1>
+            <p>{info}</p>
+            {post_html}
+            {photos_html}
+            <footer>
+              <p><i>{scraped_from}</i></p>
+            </footer>
+          </article>
+          {navigation_html}
+        </main>
+        {lightbox_html}
     </body>
 </html>"#,
-        post_html = output_post_html,
+        post_html = page.post_html,
+        photos_html = if options.image_pages || options.lightbox {
+            photos::make_photo_strip_html(page, options.image_pages, text)
+        } else {
+            String::new()
+        },
+        lightbox_html = if options.lightbox && !page.images.is_empty() {
+            photos::make_lightbox_html(text)
+        } else {
+            String::new()
+        },
         title = page.title,
         info = info_pieces.join(", "),
-        scrape_date = page.scrape_date,
-        original_url = page.original_url
+        head = layout::head_html(options),
+        skip_link = layout::skip_link_html(text),
+        metadata = metadata::make_head_metadata(page, options),
+        lang = text.lang,
+        scraped_from = locale::fill(
+            text.scraped_from,
+            &[
+                (
+                    "date",
+                    &format!(
+                        r#"<time datetime="{}">{}</time>"#,
+                        page.scrape_date.to_rfc3339(),
+                        options.format_datetime(options.display_datetime(page.scrape_date))
+                    )
+                ),
+                (
+                    "url",
+                    &format!(r#"<a href="{0}">{0}</a>"#, page.original_url)
+                ),
+            ]
+        ),
     )
 }
 
@@ -278,6 +636,8 @@ fn get_initial_text_from_html(html: &String) -> String {
 fn create_page_from_mhtml(
     path: &std::path::PathBuf,
     output_dir: &std::path::PathBuf,
+    image_store: &image_store::ImageStore,
+    options: &SiteOptions,
 ) -> Result<Page, io::Error> {
     let mut page: Page = Default::default();
 
@@ -286,75 +646,173 @@ fn create_page_from_mhtml(
     page.scrape_date = doc.date;
     page.original_url = doc.location;
 
-    let mut flattened_title = page.title.replace("/", "_").replace(" ", "_");
-    flattened_title.retain(|c| c.is_ascii_alphanumeric() || c == '_');
-    flattened_title.make_ascii_lowercase();
-    let basename = format!(
-        "{}_{:x}",
-        flattened_title,
-        calculate_hash(&page.original_url)
-    );
+    let basename = make_basename(&page.title, &page.original_url);
     page.output_file = format!("{}.html", basename);
-    page.images_dir = format!("{}_images", basename);
 
     let mut image_to_path: HashMap<String, String> = HashMap::new();
-    let mut image_to_thumbnail: HashMap<String, String> = HashMap::new();
-    let mut num_images = 0;
-    let images_dir = output_dir.join(&page.images_dir);
-    fs::create_dir_all(&images_dir)?;
+    let mut image_to_stored: HashMap<String, image_store::StoredImage> = HashMap::new();
 
     if doc.pieces.is_empty() {
         return Err(invalid_data_err("MHTML has no data"));
     };
     let post = parse_post_from_mhtml_piece(&doc.pieces[0])?;
+    page.author = post
+        .author
+        .as_ref()
+        .map(|author| authors::canonical_author(author, &options.author_aliases));
 
     for piece in doc.pieces.iter().skip(1) {
-        if piece.content_type == "image/jpeg" && post.image_urls.contains(&piece.location) {
-            num_images += 1;
-            let filename = format!("{:03}.jpeg", num_images);
-            image_to_path.insert(
-                piece.location.clone(),
-                format!("{}/{}", &page.images_dir, &filename),
-            );
-            fs::write(images_dir.join(&filename), &piece.bytes)?;
-            let thumbnail_filename = format!("{:03}_thumbnail.jpeg", num_images);
-            thumbnail::create_thumbnail(&piece.bytes, &images_dir.join(&thumbnail_filename));
-            image_to_thumbnail.insert(
-                piece.location.clone(),
-                format!("{}/{}", page.images_dir, thumbnail_filename),
-            );
+        if piece.content_type.starts_with("image/") && post.image_urls.contains(&piece.location) {
+            let stored = image_store.add(&piece.bytes, &piece.content_type, options)?;
+            page.warnings.extend(stored.warnings.iter().cloned());
+            if let Some(path) = &stored.path {
+                image_to_path.insert(piece.location.clone(), path.clone());
+            }
+            image_to_stored.insert(piece.location.clone(), stored);
+        }
+    }
+    let mut missing_images =
+        missing_images::find_missing(&post.image_urls, |url| image_to_stored.contains_key(url));
+    if let Some(cache_dir) = &options.image_cache {
+        for url in &missing_images {
+            if let Some((contents, content_type)) = missing_images::read_cached(cache_dir, url) {
+                let stored = image_store.add(&contents, &content_type, options)?;
+                page.warnings.extend(stored.warnings.iter().cloned());
+                if let Some(path) = &stored.path {
+                    image_to_path.insert(url.clone(), path.clone());
+                }
+                image_to_stored.insert(url.clone(), stored);
+            }
         }
+        missing_images.retain(|url| !image_to_stored.contains_key(url));
     }
+    for url in &missing_images {
+        page.warnings
+            .push(format!("image {url} is missing from the MHTML"));
+    }
+    // Original URLs of the images, parallel to page.images.
+    let mut image_urls: Vec<String> = Vec::new();
+    // When the photos were taken, which can be known even if stripped from page.image_metadata.
+    let mut taken_at: Vec<NaiveDateTime> = Vec::new();
+    // Images left out of the site, which are only shown as placeholders in the post.
+    let mut left_out: Vec<String> = Vec::new();
     for image_url in &post.image_urls {
-        if let Some(thumbnail_path) = image_to_thumbnail.get(image_url) {
-            page.thumbnails.push(thumbnail_path.clone());
+        let Some(stored) = image_to_stored.get(image_url) else {
+            continue;
+        };
+        if let Some(path) = &stored.path {
+            image_urls.push(image_url.clone());
+            page.images.push(path.clone());
+            page.thumbnails.push(stored.thumbnail.clone());
+            page.thumbnail_alternatives
+                .push(stored.thumbnail_alternative.clone());
+            page.image_versions.push(stored.versions.clone());
+            page.image_metadata.push(stored.metadata.clone());
+            page.image_hashes.push(stored.perceptual_hash);
+            taken_at.extend(stored.taken_at);
+        } else {
+            left_out.push(image_url.clone());
         }
     }
-    if let Some(post_date) = post.date {
-        page.post_date = post_date;
+    if let Some(posted) = post.date {
+        // Assume the post's time is in the same timezone as the scrape date.
+        let posted = page
+            .scrape_date
+            .offset()
+            .from_local_datetime(&posted)
+            .unwrap();
+        let posted = options.display_datetime(posted);
+        page.post_date = posted.date();
+        page.post_time = Some(posted.time());
     } else if let Some(title_date) = date_from_title(page.title.as_bytes()) {
         page.post_date = title_date;
+    } else if let Some(taken_at) = taken_at
+        .into_iter()
+        .min()
+        .filter(|_| options.exif_post_date)
+    {
+        page.post_date = taken_at.date();
+        page.post_time = Some(taken_at.time());
     } else {
-        page.post_date = page.scrape_date.naive_local().date();
+        page.post_date = options.display_datetime(page.scrape_date).date();
     }
 
-    let output_html = make_output_html_for_post(&post, &page, &image_to_path);
-    fs::write(output_dir.join(&page.output_file), output_html.as_bytes())?;
     page.initial_text = get_initial_text_from_html(&post.html);
+    page.post_html = rewrite_post_html(&post, &image_to_path);
+    if !left_out.is_empty() {
+        page.post_html = missing_images::use_placeholders(
+            &page.post_html,
+            &left_out,
+            options.locale.catalog().missing_image,
+        );
+    }
+    if options.placeholder_missing_images {
+        page.post_html = missing_images::use_placeholders(
+            &page.post_html,
+            &missing_images,
+            options.locale.catalog().missing_image,
+        );
+    }
+    page.image_captions = photos::find_captions(&page.post_html, page.images.len());
+    page.post_html =
+        accessibility::add_alt_text(&page.post_html, &page.image_captions, &image_urls);
+    page.post_html = photos::add_srcsets(&page.post_html, &page.image_versions);
+    for src in accessibility::images_missing_alt(&page.post_html) {
+        page.warnings.push(format!("image {src} has no alt text"));
+    }
+    if options.image_pages {
+        photos::write_image_pages(&page, output_dir, options)?;
+    }
     page.i_text = post.i_text;
+    page.missing_images = missing_images;
 
     Ok(page)
 }
 
+/// Writes the HTML for each post, given pages sorted in their final order.
+fn write_post_pages(
+    pages: &[Page],
+    output_dir: &Path,
+    options: &SiteOptions,
+) -> Result<(), io::Error> {
+    let related = navigation::find_related(pages, &options.tag_config);
+    for (i, page) in pages.iter().enumerate() {
+        let navigation_html =
+            navigation::make_navigation_html(pages, i, &related[i], options.locale.catalog());
+        let output_html = make_output_html_for_post(page, &navigation_html, options);
+        fs::write(output_dir.join(&page.output_file), output_html.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Contents of posts.json.
+#[derive(Serialize)]
+struct PostsJson<'a> {
+    schema_version: u32,
+    /// Name and version of the program which wrote the file.
+    generator: String,
+    build_time: DateTime<Utc>,
+    site_title: &'a str,
+    /// Most recent first.
+    posts: &'a [Page],
+}
+
 struct Site {
     /// Number of pages generated from posts.
     num_pages: usize,
+    /// Number of distinct authors across the posts.
+    num_authors: usize,
+    /// Problems worth fixing in the input, each prefixed with the affected output file.
+    warnings: Vec<String>,
 }
 
 fn create_site_from_mhtml_dir(
     input_dir: &std::path::PathBuf,
     output_dir: &std::path::PathBuf,
+    options: SiteOptions,
 ) -> Result<Site, io::Error> {
+    let options = Arc::new(options);
+    let image_store = Arc::new(image_store::ImageStore::new(output_dir)?);
     let mut num_pages = 0;
     // TODO: make the number of workers configurable.
     let pool = threadpool::ThreadPool::new(5);
@@ -365,10 +823,17 @@ fn create_site_from_mhtml_dir(
             num_pages += 1;
             let path = entry.path();
             let my_output_dir = output_dir.clone();
+            let my_options = Arc::clone(&options);
+            let my_image_store = Arc::clone(&image_store);
             let sender = sender.clone();
             pool.execute(move || {
                 sender
-                    .send(create_page_from_mhtml(&path, &my_output_dir))
+                    .send(create_page_from_mhtml(
+                        &path,
+                        &my_output_dir,
+                        &my_image_store,
+                        &my_options,
+                    ))
                     .unwrap();
             });
         }
@@ -380,30 +845,125 @@ fn create_site_from_mhtml_dir(
     pool.join();
 
     pages.sort_by(|a, b| {
-        if a.post_date == b.post_date {
+        if (a.post_date, a.post_time) == (b.post_date, b.post_time) {
             a.title.partial_cmp(&b.title).unwrap()
         } else {
             // Put more recent posts first
-            b.post_date.partial_cmp(&a.post_date).unwrap()
+            (b.post_date, b.post_time)
+                .partial_cmp(&(a.post_date, a.post_time))
+                .unwrap()
         }
     });
+    let posts_json = PostsJson {
+        schema_version: POSTS_SCHEMA_VERSION,
+        generator: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+        build_time: Utc::now(),
+        site_title: &options.site_title,
+        posts: &pages,
+    };
     fs::write(
         output_dir.join("posts.json"),
-        serde_json::to_string(&pages)?,
+        serde_json::to_string(&posts_json)?,
     )?;
+    layout::write_stylesheets(output_dir, &options)?;
+    thumbnail::write_placeholder(output_dir)?;
+    write_post_pages(&pages, output_dir, &options)?;
+    index::write_index_page(&pages, output_dir, &options)?;
+    search::write_search_files(&pages, output_dir, &options)?;
+    let clusters = if options.duplicates_report || options.mark_duplicates {
+        duplicates::find_clusters(&pages)
+    } else {
+        Vec::new()
+    };
+    gallery::write_gallery_pages(&pages, &clusters, output_dir, &options)?;
+    if options.duplicates_report {
+        duplicates::write_report(&pages, &clusters, output_dir, &options)?;
+    }
+    if missing_images::any_missing(&pages) {
+        missing_images::write_report(&pages, output_dir, &options)?;
+    }
+    calendar::write_calendar_pages(&pages, output_dir, &options)?;
+    let num_authors = authors::write_author_pages(&pages, output_dir, &options)?;
+    if options.tag_pages {
+        tags::write_tag_pages(&pages, output_dir, &options)?;
+    }
+    feeds::write_feeds(&pages, output_dir, &options)?;
+    sitemap::write_robots_txt(output_dir, &options)?;
+    if options.base_url.is_some() {
+        sitemap::write_sitemap(&pages, output_dir, &options)?;
+    }
 
+    let mut warnings: Vec<String> = pages
+        .iter()
+        .flat_map(|page| {
+            page.warnings
+                .iter()
+                .map(|warning| format!("{}: {}", page.output_file, warning))
+        })
+        .collect();
+    let num_without_webp = image_store.num_without_webp();
+    if num_without_webp > 0 {
+        warnings.push(format!(
+            "no WebP copies made of {num_without_webp} images without transparency, for which \
+             lossless WebP would be larger than JPEG"
+        ));
+    }
     Ok(Site {
         num_pages,
+        num_authors,
+        warnings,
     })
 }
 
 fn main() {
     let args = Cli::parse();
+    let mut options: SiteOptions = Default::default();
+    if let Some(path) = &args.author_aliases {
+        options.author_aliases = authors::load_aliases(path).unwrap();
+    }
+    options.tag_pages = args.tag_pages;
+    if let Some(path) = &args.tag_config {
+        options.tag_config = tags::load_config(path).unwrap();
+    }
+    options.base_url = args.base_url;
+    options.site_title = args.site_title;
+    options.image_pages = args.image_pages;
+    options.lightbox = args.lightbox;
+    options.include_gps = args.include_gps;
+    options.exif_post_date = args.exif_post_date;
+    options.strip_metadata = args.strip_metadata;
+    options.image_cache = args.image_cache;
+    options.placeholder_missing_images = args.placeholder_missing_images;
+    options.duplicates_report = args.duplicates_report;
+    options.mark_duplicates = args.mark_duplicates;
+    options.derivatives = thumbnail::DerivativeOptions {
+        thumbnail_height: args.thumbnail_height,
+        widths: args.image_widths,
+        format: args.image_format,
+        quality: args.image_quality,
+    };
+    options.locale = args.locale;
+    options.timezone = args.timezone;
+    options.date_format = args.date_format;
+    options.datetime_format = args.datetime_format;
+    if let Some(path) = &args.robots_txt {
+        options.robots_txt = Some(fs::read_to_string(path).unwrap());
+    }
+    if let Some(path) = &args.theme {
+        options.theme_css = Some(fs::read_to_string(path).unwrap());
+    }
+    if let Some(path) = &args.extra_css {
+        options.extra_css = Some(fs::read_to_string(path).unwrap());
+    }
     fs::create_dir_all(&args.output_dir).unwrap();
-    let site = create_site_from_mhtml_dir(&args.input_dir, &args.output_dir).unwrap();
+    let site = create_site_from_mhtml_dir(&args.input_dir, &args.output_dir, options).unwrap();
+    for warning in &site.warnings {
+        eprintln!("Warning: {warning}");
+    }
     println!(
-        "Generated {:?} pages under {:?}",
+        "Generated {:?} pages by {:?} authors under {:?}",
         site.num_pages,
+        site.num_authors,
         args.output_dir.display()
     );
 }
@@ -412,6 +972,26 @@ fn main() {
 mod tests {
     use super::*;
 
+    #[test]
+    fn image_extension_from_content_type() {
+        assert_eq!(image_extension("image/jpeg"), "jpeg");
+        assert_eq!(image_extension("image/png"), "png");
+        assert_eq!(image_extension("image/svg+xml"), "svg");
+        assert_eq!(image_extension("image/x-ms-bmp"), "bmp");
+    }
+
+    #[test]
+    fn parse_strftime_format_rejects_invalid() {
+        assert_eq!(
+            parse_strftime_format("%d/%m/%Y %H:%M"),
+            Ok(String::from("%d/%m/%Y %H:%M"))
+        );
+        assert!(parse_strftime_format("%Q").is_err());
+        let args = |format| ["gg", "-i", "in", "-o", "out", "--date_format", format];
+        assert!(Cli::try_parse_from(args("%Y")).is_ok());
+        assert!(Cli::try_parse_from(args("%Q")).is_err());
+    }
+
     #[test]
     fn date_from_title_yy() {
         assert_eq!(
@@ -437,7 +1017,15 @@ mod tests {
     fn date_from_html_pm() {
         assert_eq!(
             date_from_html(br#"<span class="zX2W9c">Jul 13, 2023, 7:31:18\u{202F}PM</span>"#),
-            NaiveDate::from_ymd_opt(2023, 7, 13)
+            NaiveDate::from_ymd_opt(2023, 7, 13).and_then(|date| date.and_hms_opt(19, 31, 18))
+        );
+    }
+
+    #[test]
+    fn date_from_html_midnight() {
+        assert_eq!(
+            date_from_html("<span>Jan 2, 2024, 12:05:00\u{202F}AM</span>".as_bytes()),
+            NaiveDate::from_ymd_opt(2024, 1, 2).and_then(|date| date.and_hms_opt(0, 5, 0))
         );
     }
 
@@ -0,0 +1,151 @@
+// Open Graph, Twitter card and JSON-LD metadata so that links to posts get previews.
+
+use crate::{Page, SiteOptions};
+
+use htmlize::escape_attribute;
+use serde_json::json;
+
+fn meta_property(property: &str, content: &str) -> String {
+    format!(
+        "<meta property=\"{}\" content=\"{}\">\n",
+        property,
+        escape_attribute(content)
+    )
+}
+
+fn meta_name(name: &str, content: &str) -> String {
+    format!(
+        "<meta name=\"{}\" content=\"{}\">\n",
+        name,
+        escape_attribute(content)
+    )
+}
+
+/// When the post was published in ISO 8601 format, or just its date if the time isn't known.
+fn published_time(page: &Page, options: &SiteOptions) -> String {
+    match options.post_datetime(page) {
+        Some(datetime) => datetime.to_rfc3339(),
+        None => page.post_date.format("%Y-%m-%d").to_string(),
+    }
+}
+
+/// Describes the post as a schema.org DiscussionForumPosting.
+fn make_json_ld(page: &Page, options: &SiteOptions) -> String {
+    let mut posting = json!({
+        "@context": "https://schema.org",
+        "@type": "DiscussionForumPosting",
+        "headline": page.title,
+        "text": page.initial_text,
+        "datePublished": published_time(page, options),
+        "url": page.original_url,
+    });
+    if let Some(author) = &page.author {
+        posting["author"] = json!({"@type": "Person", "name": author});
+    }
+    if options.base_url.is_some() {
+        posting["mainEntityOfPage"] = json!(options.url_for(&page.output_file));
+    }
+    if !page.images.is_empty() {
+        let images: Vec<String> = page.images.iter().map(|i| options.url_for(i)).collect();
+        posting["image"] = json!(images);
+    }
+    // Keep the JSON from closing the script element early.
+    posting.to_string().replace("</", "<\\/")
+}
+
+/// Returns tags for the head of a post page. Link previews generally need absolute URLs,
+/// so og:url and the image URLs are only fully useful when base_url is set.
+pub fn make_head_metadata(page: &Page, options: &SiteOptions) -> String {
+    let mut html = String::new();
+    html.push_str(&meta_name("description", &page.initial_text));
+    html.push_str(&meta_property("og:type", "article"));
+    html.push_str(&meta_property("og:title", &page.title));
+    html.push_str(&meta_property("og:description", &page.initial_text));
+    html.push_str(&meta_property("og:site_name", &options.site_title));
+    if options.base_url.is_some() {
+        html.push_str(&meta_property(
+            "og:url",
+            &options.url_for(&page.output_file),
+        ));
+    }
+    let image = page.images.first().map(|i| options.url_for(i));
+    if let Some(image) = &image {
+        html.push_str(&meta_property("og:image", image));
+    }
+    html.push_str(&meta_property(
+        "article:published_time",
+        &published_time(page, options),
+    ));
+    if let Some(author) = &page.author {
+        html.push_str(&meta_property("article:author", author));
+    }
+    html.push_str(&meta_name(
+        "twitter:card",
+        if image.is_some() {
+            "summary_large_image"
+        } else {
+            "summary"
+        },
+    ));
+    html.push_str(&meta_name("twitter:title", &page.title));
+    html.push_str(&meta_name("twitter:description", &page.initial_text));
+    if let Some(image) = &image {
+        html.push_str(&meta_name("twitter:image", image));
+    }
+    html.push_str(&format!(
+        "<script type=\"application/ld+json\">{}</script>",
+        make_json_ld(page, options)
+    ));
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{DateTime, NaiveDate, NaiveTime};
+
+    fn heron_page() -> Page {
+        Page {
+            title: String::from("Heron \"big\""),
+            author: Some(String::from("Jane")),
+            original_url: String::from("https://groups.google.com/g/x/c/1"),
+            output_file: String::from("heron_1.html"),
+            post_date: NaiveDate::from_ymd_opt(2023, 7, 19).unwrap(),
+            initial_text: String::from("A heron </script>"),
+            images: vec![String::from("heron_1_images/001.jpeg")],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn head_metadata_uses_absolute_image_url() {
+        let options = SiteOptions {
+            base_url: Some(String::from("https://example.com")),
+            ..Default::default()
+        };
+        let html = make_head_metadata(&heron_page(), &options);
+        assert!(html.contains(r#"<meta property="og:title" content="Heron &quot;big&quot;">"#));
+        assert!(html.contains(
+            r#"<meta property="og:image" content="https://example.com/heron_1_images/001.jpeg">"#
+        ));
+        assert!(html.contains(r#"<meta property="article:published_time" content="2023-07-19">"#));
+        assert!(html.contains(r#"<meta name="twitter:card" content="summary_large_image">"#));
+    }
+
+    #[test]
+    fn json_ld_is_escaped_for_script() {
+        let page = Page {
+            scrape_date: DateTime::parse_from_rfc3339("2023-07-20T09:00:00+02:00").unwrap(),
+            post_time: NaiveTime::from_hms_opt(14, 30, 0),
+            ..heron_page()
+        };
+        let json_ld = make_json_ld(&page, &Default::default());
+        assert!(!json_ld.contains("</script>"));
+        let value: serde_json::Value = serde_json::from_str(&json_ld).unwrap();
+        assert_eq!(value["@type"], "DiscussionForumPosting");
+        assert_eq!(value["author"]["name"], "Jane");
+        assert_eq!(value["url"], "https://groups.google.com/g/x/c/1");
+        assert_eq!(value["datePublished"], "2023-07-19T14:30:00+02:00");
+    }
+}
@@ -0,0 +1,193 @@
+// Images which posts refer to but which aren't in their MHTML, usually because the page hadn't
+// finished loading when it was saved.
+
+use crate::{layout, locale, thumbnail, Page, SiteOptions};
+
+use htmlize::{escape_attribute, escape_text};
+use lol_html::{element, rewrite_str, RewriteStrSettings};
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const MISSING_IMAGES_FILE: &str = "missing_images.html";
+
+/// Returns the URLs in `image_urls` which weren't found, in order and without repeats. Inline
+/// `data:` images can't be missing.
+pub fn find_missing(image_urls: &[String], is_found: impl Fn(&str) -> bool) -> Vec<String> {
+    let mut missing: Vec<String> = Vec::new();
+    for url in image_urls {
+        if !url.starts_with("data:") && !is_found(url) && !missing.contains(url) {
+            missing.push(url.clone());
+        }
+    }
+    missing
+}
+
+/// Name within a cache dir of the file downloaded from `url`: the URL with every byte other
+/// than ASCII letters, digits, '-', '.' and '_' percent-encoded, as in
+/// "https%3A%2F%2Flh3.googleusercontent.com%2Fabc".
+pub fn cache_file_name(url: &str) -> String {
+    let mut name = String::new();
+    for byte in url.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._".contains(&byte) {
+            name.push(char::from(byte));
+        } else {
+            name.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    name
+}
+
+/// Reads the copy of the image at `url` from `cache_dir`, returning its contents and MIME type
+/// if there's a copy in a recognized format.
+pub fn read_cached(cache_dir: &Path, url: &str) -> Option<(Vec<u8>, String)> {
+    let contents = fs::read(cache_dir.join(cache_file_name(url))).ok()?;
+    let content_type = match image::guess_format(&contents) {
+        Ok(format) => format.to_mime_type(),
+        Err(_) if contents.windows(4).any(|window| window == b"<svg") => "image/svg+xml",
+        Err(_) => return None,
+    };
+    Some((contents, String::from(content_type)))
+}
+
+/// Points each image in `post_html` with one of the `missing` URLs at the placeholder, giving
+/// it `alt` unless it already has alt text.
+pub fn use_placeholders(post_html: &str, missing: &[String], alt: &str) -> String {
+    let element_content_handlers = vec![element!("img[src]", |el| {
+        let src = el.get_attribute("src").unwrap().replace("&amp;", "&");
+        if !missing.contains(&src) {
+            return Ok(());
+        }
+        el.set_attribute("src", thumbnail::PLACEHOLDER_FILE)
+            .unwrap();
+        if el
+            .get_attribute("alt")
+            .is_none_or(|existing| existing.trim().is_empty())
+        {
+            el.set_attribute("alt", alt).unwrap();
+        }
+        Ok(())
+    })];
+    let output_html = rewrite_str(
+        post_html,
+        RewriteStrSettings {
+            element_content_handlers,
+            ..RewriteStrSettings::new()
+        },
+    )
+    .unwrap();
+    output_html
+}
+
+/// Whether any post has images missing, so there's something to report.
+pub fn any_missing(pages: &[Page]) -> bool {
+    pages.iter().any(|page| !page.missing_images.is_empty())
+}
+
+fn make_report_html(pages: &[Page], options: &SiteOptions) -> String {
+    let text = options.locale.catalog();
+    let pages: Vec<&Page> = pages
+        .iter()
+        .filter(|page| !page.missing_images.is_empty())
+        .collect();
+    let mut body = format!(
+        "<p>{}</p>\n",
+        escape_text(locale::fill(
+            text.missing_image_count,
+            &[("count", &pages.len().to_string())]
+        ))
+    );
+    for page in pages {
+        body.push_str(&format!(
+            "        <h2><a href=\"{href}\">{title}</a> ({date})</h2>\n        <ul>\n",
+            href = escape_attribute(&page.output_file),
+            title = escape_text(&page.title),
+            date = layout::render_date(page.post_date, options),
+        ));
+        for url in &page.missing_images {
+            body.push_str(&format!(
+                "          <li><a href=\"{href}\">{url}</a></li>\n",
+                href = escape_attribute(url),
+                url = escape_text(url),
+            ));
+        }
+        body.push_str("        </ul>\n");
+    }
+    layout::render_page(text.missing_images, &body, options)
+}
+
+/// Writes a page listing the images missing from each post, for finding pages to save again.
+pub fn write_report(
+    pages: &[Page],
+    output_dir: &Path,
+    options: &SiteOptions,
+) -> Result<(), io::Error> {
+    fs::write(
+        output_dir.join(MISSING_IMAGES_FILE),
+        make_report_html(pages, options),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn read_cached_finds_file_by_url() {
+        let test_dir = TestDir::new("read_cached_finds_file_by_url");
+        let cache_dir = test_dir.path();
+        let url = "https://lh3.googleusercontent.com/a1=w400?x&y";
+        assert_eq!(
+            cache_file_name(url),
+            "https%3A%2F%2Flh3.googleusercontent.com%2Fa1%3Dw400%3Fx%26y"
+        );
+        fs::write(cache_dir.join(cache_file_name(url)), b"<svg/>").unwrap();
+        assert_eq!(
+            read_cached(cache_dir, url),
+            Some((b"<svg/>".to_vec(), String::from("image/svg+xml")))
+        );
+        assert_eq!(read_cached(cache_dir, "https://x/not-cached"), None);
+    }
+
+    #[test]
+    fn use_placeholders_keeps_found_images() {
+        let urls = vec![
+            String::from("https://x/a"),
+            String::from("data:image/png;base64,AAAA"),
+            String::from("https://x/b"),
+            String::from("https://x/a"),
+        ];
+        let missing = find_missing(&urls, |url| url == "https://x/b");
+        assert_eq!(missing, vec![String::from("https://x/a")]);
+        let html = r#"<img src="https://x/a"><img src="images/b.jpeg" id="img-1">"#;
+        assert_eq!(
+            use_placeholders(html, &missing, r#"The "heron""#),
+            r#"<img src="placeholder_thumbnail.svg" alt="The &quot;heron&quot;"><img src="images/b.jpeg" id="img-1">"#
+        );
+    }
+
+    #[test]
+    fn report_lists_posts_with_missing_images() {
+        let pages = vec![
+            Page {
+                title: String::from("Heron & egret"),
+                output_file: String::from("heron_1.html"),
+                missing_images: vec![String::from("https://x/a?w=1&h=2")],
+                ..Default::default()
+            },
+            Page {
+                title: String::from("Complete"),
+                output_file: String::from("complete_2.html"),
+                ..Default::default()
+            },
+        ];
+        assert!(any_missing(&pages));
+        assert!(!any_missing(&pages[1..]));
+        let html = make_report_html(&pages, &Default::default());
+        assert!(html.contains(r#"<a href="heron_1.html">Heron &amp; egret</a>"#));
+        assert!(html.contains(r#"<a href="https://x/a?w=1&amp;h=2">https://x/a?w=1&amp;h=2</a>"#));
+        assert!(!html.contains("complete_2.html"));
+    }
+}
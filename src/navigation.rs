@@ -0,0 +1,164 @@
+// Links between post pages: previous/next, back to the index, and related posts.
+
+use crate::locale::{self, Catalog};
+use crate::tags::{self, TagConfig};
+use crate::{index, Page};
+
+use htmlize::{escape_attribute, escape_text};
+
+use std::collections::HashMap;
+
+/// Maximum number of related posts listed on each post page.
+const MAX_RELATED: usize = 5;
+/// Each tag shared with another post counts this much more than having the same author.
+const SHARED_TAG_WEIGHT: usize = 2;
+const SAME_AUTHOR_WEIGHT: usize = 1;
+
+/// For each page, returns the indices of the most closely related other pages, best first.
+/// Pages are related by sharing tags (see tags::tag_for_text) or by having the same author.
+pub fn find_related(pages: &[Page], tag_config: &TagConfig) -> Vec<Vec<usize>> {
+    let page_tags: Vec<Vec<String>> = pages
+        .iter()
+        .map(|page| {
+            let mut keys: Vec<String> = page
+                .i_text
+                .iter()
+                .filter_map(|text| tags::tag_for_text(text, tag_config))
+                .map(|(key, _name)| key)
+                .collect();
+            keys.sort();
+            keys.dedup();
+            keys
+        })
+        .collect();
+    let mut by_tag: HashMap<&str, Vec<usize>> = HashMap::new();
+    let mut by_author: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, page) in pages.iter().enumerate() {
+        for key in &page_tags[i] {
+            by_tag.entry(key.as_str()).or_default().push(i);
+        }
+        if let Some(author) = &page.author {
+            by_author.entry(author.as_str()).or_default().push(i);
+        }
+    }
+
+    let mut related: Vec<Vec<usize>> = Vec::with_capacity(pages.len());
+    for (i, page) in pages.iter().enumerate() {
+        let mut scores: HashMap<usize, usize> = HashMap::new();
+        for key in &page_tags[i] {
+            for &j in &by_tag[key.as_str()] {
+                *scores.entry(j).or_default() += SHARED_TAG_WEIGHT;
+            }
+        }
+        if let Some(author) = &page.author {
+            for &j in &by_author[author.as_str()] {
+                *scores.entry(j).or_default() += SAME_AUTHOR_WEIGHT;
+            }
+        }
+        scores.remove(&i);
+        let mut ranked: Vec<(usize, usize)> = scores.into_iter().collect();
+        // Highest score first, then closest in time.
+        ranked.sort_by_key(|&(j, score)| (std::cmp::Reverse(score), j.abs_diff(i), j));
+        related.push(
+            ranked
+                .into_iter()
+                .take(MAX_RELATED)
+                .map(|(j, _)| j)
+                .collect(),
+        );
+    }
+    related
+}
+
+fn make_link(page: &Page, rel: &str, template: &str) -> String {
+    format!(
+        r#"<a href="{href}" rel="{rel}">{label}</a>"#,
+        href = escape_attribute(&page.output_file),
+        label = locale::fill(template, &[("title", &escape_text(&page.title))]),
+    )
+}
+
+/// Makes the navigation for the page at `index` within `pages`, which are sorted most recent
+/// first, so "previous" (older) is the following page.
+pub fn make_navigation_html(
+    pages: &[Page],
+    index: usize,
+    related: &[usize],
+    text: &Catalog,
+) -> String {
+    let mut links: Vec<String> = Vec::new();
+    if let Some(newer) = index.checked_sub(1).map(|i| &pages[i]) {
+        links.push(make_link(newer, "next", text.next_post));
+    }
+    links.push(format!(
+        r#"<a href="{}">{}</a>"#,
+        index::INDEX_FILE,
+        text.all_posts
+    ));
+    if let Some(older) = pages.get(index + 1) {
+        links.push(make_link(older, "prev", text.previous_post));
+    }
+    let mut html = format!("<nav>{}</nav>", links.join(" | "));
+    if !related.is_empty() {
+        html.push_str(&format!("\n<h2>{}</h2>\n<ul>\n", text.related_posts));
+        for &j in related {
+            html.push_str(&format!(
+                "<li><a href=\"{}\">{}</a></li>\n",
+                escape_attribute(&pages[j].output_file),
+                escape_text(&pages[j].title)
+            ));
+        }
+        html.push_str("</ul>");
+    }
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locale::Locale;
+
+    fn page(author: &str, i_text: &[&str]) -> Page {
+        Page {
+            author: Some(String::from(author)),
+            i_text: i_text.iter().map(|s| String::from(*s)).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn find_related_prefers_shared_tags() {
+        let pages = vec![
+            page("Ann", &["Heron"]),
+            page("Bob", &[]),
+            page("Bob", &["heron", "Lac Blanc"]),
+            page("Cat", &["Lac Blanc"]),
+            page("Dan", &[]),
+        ];
+        let related = find_related(&pages, &Default::default());
+        assert_eq!(related[0], vec![2]);
+        assert_eq!(related[1], vec![2]);
+        assert_eq!(related[2], vec![3, 0, 1]);
+        assert!(related[4].is_empty());
+    }
+
+    #[test]
+    fn navigation_links_neighbors() {
+        let pages: Vec<Page> = ["c", "b", "a"]
+            .iter()
+            .map(|name| Page {
+                title: name.to_uppercase(),
+                output_file: format!("{name}.html"),
+                ..Default::default()
+            })
+            .collect();
+        assert_eq!(
+            make_navigation_html(&pages, 1, &[], Locale::En.catalog()),
+            r#"<nav><a href="c.html" rel="next">Next: C</a> | <a href="index.html">All posts</a> | <a href="a.html" rel="prev">Previous: A</a></nav>"#
+        );
+        assert_eq!(
+            make_navigation_html(&pages, 0, &[], Locale::En.catalog()),
+            r#"<nav><a href="index.html">All posts</a> | <a href="b.html" rel="prev">Previous: B</a></nav>"#
+        );
+    }
+}
@@ -0,0 +1,431 @@
+// Ways to step through the photos in a post: static per-image pages and an optional viewer.
+
+use crate::image_metadata::ImageMetadata;
+use crate::locale::{self, Catalog};
+use crate::thumbnail::SizedImage;
+use crate::{feeds, get_text_from_html, layout, Page, SiteOptions, MAX_I_TEXT_LEN, MIN_I_TEXT_LEN};
+
+use htmlize::{escape_attribute, escape_text};
+use lol_html::html_content::ContentType;
+use lol_html::{element, rewrite_str, RewriteStrSettings};
+
+use std::sync::OnceLock;
+
+/// Name within output dir of the page for the image with id `img-{number}` in a post.
+pub fn image_page_file(page: &Page, number: usize) -> String {
+    format!(
+        "{}_img_{}.html",
+        page.output_file.trim_end_matches(".html"),
+        number
+    )
+}
+
+/// Finds a caption for each localized image (those with an `img-N` id) in post HTML: the
+/// first phrase in italics between the image and the next image, if any.
+pub fn find_captions(post_html: &str, num_images: usize) -> Vec<Option<String>> {
+    static IMG_RE_LOCK: OnceLock<regex::Regex> = OnceLock::new();
+    static I_RE_LOCK: OnceLock<regex::Regex> = OnceLock::new();
+    let img_re = IMG_RE_LOCK
+        .get_or_init(|| regex::Regex::new(r#"<img\b[^>]*\bid="img-(\d+)"[^>]*>"#).unwrap());
+    let i_re = I_RE_LOCK.get_or_init(|| regex::Regex::new(r#"(?s)<i>(.*?)</i>"#).unwrap());
+    let mut captions: Vec<Option<String>> = vec![None; num_images];
+    let matches: Vec<regex::Captures> = img_re.captures_iter(post_html).collect();
+    for (i, captures) in matches.iter().enumerate() {
+        let number: usize = captures[1].parse().unwrap();
+        if number == 0 || number > num_images {
+            continue;
+        }
+        let start = captures.get(0).unwrap().end();
+        let end = matches
+            .get(i + 1)
+            .map_or(post_html.len(), |next| next.get(0).unwrap().start());
+        if let Some(i_captures) = i_re.captures(&post_html[start..end]) {
+            let text = get_text_from_html(&i_captures[1]);
+            if (MIN_I_TEXT_LEN..=MAX_I_TEXT_LEN).contains(&text.len()) {
+                captions[number - 1] = Some(text);
+            }
+        }
+    }
+    captions
+}
+
+/// How wide images are shown in posts and on image pages, given the theme's maximum body width.
+const IMAGE_SIZES: &str = "(max-width: 48rem) 100vw, 48rem";
+
+/// Makes a srcset listing the versions of an image, if it has more than one.
+fn srcset(versions: &[SizedImage]) -> Option<String> {
+    if versions.len() < 2 {
+        return None;
+    }
+    let candidates: Vec<String> = versions
+        .iter()
+        .map(|version| format!("{} {}w", version.path, version.width))
+        .collect();
+    Some(candidates.join(", "))
+}
+
+/// Makes a source element listing the versions of an image in WebP or AVIF format, if any.
+/// Browsers which support the format only choose from this list, so versions without a copy
+/// in it, such as the image itself, are listed as they are.
+fn alternative_source_html(versions: &[SizedImage]) -> Option<String> {
+    let first = versions
+        .iter()
+        .find_map(|version| version.alternative.as_deref())?;
+    let candidates: Vec<String> = versions
+        .iter()
+        .map(|version| {
+            let path = version.alternative.as_deref().unwrap_or(&version.path);
+            format!("{path} {}w", version.width)
+        })
+        .collect();
+    Some(format!(
+        r#"<source srcset="{}" sizes="{IMAGE_SIZES}" type="{}">"#,
+        escape_attribute(candidates.join(", ")),
+        feeds::image_mime_type(first)
+    ))
+}
+
+/// Lets browsers pick a smaller version of each localized image (those with an `img-N` id) in
+/// `post_html`, wrapping it in a picture element when there are versions in WebP or AVIF
+/// format. `versions` is indexed by N - 1.
+pub fn add_srcsets(post_html: &str, versions: &[Vec<SizedImage>]) -> String {
+    let element_content_handlers = vec![element!(r#"img[id^="img-"]"#, |el| {
+        let Some(versions) = el
+            .get_attribute("id")
+            .and_then(|id| id["img-".len()..].parse::<usize>().ok())
+            .and_then(|number| versions.get(number.checked_sub(1)?))
+        else {
+            return Ok(());
+        };
+        if let Some(srcset) = srcset(versions) {
+            el.set_attribute("srcset", &srcset).unwrap();
+            el.set_attribute("sizes", IMAGE_SIZES).unwrap();
+        }
+        if let Some(source_html) = alternative_source_html(versions) {
+            el.before(&format!("<picture>{source_html}"), ContentType::Html);
+            el.after("</picture>", ContentType::Html);
+        }
+        Ok(())
+    })];
+    let output_html = rewrite_str(
+        post_html,
+        RewriteStrSettings {
+            element_content_handlers,
+            ..RewriteStrSettings::new()
+        },
+    )
+    .unwrap();
+    output_html
+}
+
+/// Renders thumbnails linking to each photo, which the viewer script (if included) enhances.
+/// Links go to the per-image pages when they exist, otherwise straight to the images.
+pub fn make_photo_strip_html(page: &Page, link_to_image_pages: bool, text: &Catalog) -> String {
+    if page.images.is_empty() {
+        return String::new();
+    }
+    let mut html = String::from("<p class=\"photos\">\n");
+    for (i, (image, thumbnail)) in page.images.iter().zip(&page.thumbnails).enumerate() {
+        let href = if link_to_image_pages {
+            image_page_file(page, i + 1)
+        } else {
+            image.clone()
+        };
+        let caption = page
+            .image_captions
+            .get(i)
+            .cloned()
+            .flatten()
+            .unwrap_or_default();
+        // The thumbnail is the link's only content, so it needs alt text even without a caption.
+        let alt = if caption.is_empty() {
+            locale::fill(
+                text.photo_number,
+                &[
+                    ("number", &(i + 1).to_string()),
+                    ("total", &page.images.len().to_string()),
+                ],
+            )
+        } else {
+            caption.clone()
+        };
+        let img_html = format!(
+            "<img src=\"{thumbnail}\" alt=\"{alt}\">",
+            thumbnail = escape_attribute(thumbnail),
+            alt = escape_attribute(&alt),
+        );
+        let alternative = page.thumbnail_alternatives.get(i).cloned().flatten();
+        html.push_str(&format!(
+            "<a href=\"{href}\" data-full=\"{full}\" data-caption=\"{caption}\">{picture}</a>\n",
+            href = escape_attribute(&href),
+            full = escape_attribute(image),
+            caption = escape_attribute(&caption),
+            picture = layout::picture_html(&img_html, alternative.as_deref()),
+        ));
+    }
+    html.push_str("</p>");
+    html
+}
+
+/// Returns (label, value) pairs describing how an image was taken.
+fn metadata_summary(
+    metadata: &ImageMetadata,
+    options: &SiteOptions,
+) -> Vec<(&'static str, String)> {
+    let labels = &options.locale.catalog().exif_labels;
+    let mut summary: Vec<(&str, String)> = Vec::new();
+    if let Some(taken_at) = metadata.taken_at {
+        summary.push((labels.taken_at, options.format_datetime(taken_at)));
+    }
+    if let Some(camera) = &metadata.camera_model {
+        summary.push((labels.camera, camera.clone()));
+    }
+    if let Some(lens) = &metadata.lens_model {
+        summary.push((labels.lens, lens.clone()));
+    }
+    if let Some(focal_length) = metadata.focal_length_mm {
+        summary.push((labels.focal_length, format!("{focal_length} mm")));
+    }
+    if let Some(f_number) = metadata.f_number {
+        summary.push((labels.aperture, format!("f/{f_number}")));
+    }
+    if let Some(exposure) = metadata.exposure_time_s {
+        let value = if exposure < 1.0 {
+            format!("1/{} s", (1.0 / exposure).round())
+        } else {
+            format!("{exposure} s")
+        };
+        summary.push((labels.exposure, value));
+    }
+    if let Some(iso) = metadata.iso {
+        summary.push((labels.iso, iso.to_string()));
+    }
+    if let Some(gps) = &metadata.gps {
+        summary.push((
+            labels.location,
+            format!("{:.5}, {:.5}", gps.latitude, gps.longitude),
+        ));
+    }
+    summary
+}
+
+fn make_image_page_html(page: &Page, index: usize, options: &SiteOptions) -> String {
+    let text = options.locale.catalog();
+    let number = index + 1;
+    let num_images = page.images.len();
+    let caption = page.image_captions.get(index).cloned().flatten();
+    let mut links: Vec<String> = Vec::new();
+    if number > 1 {
+        links.push(format!(
+            r#"<a href="{}" rel="prev">{}</a>"#,
+            escape_attribute(image_page_file(page, number - 1)),
+            text.previous
+        ));
+    }
+    links.push(format!(
+        r##"<a href="{}#img-{}">{}</a>"##,
+        escape_attribute(&page.output_file),
+        number,
+        text.back_to_post
+    ));
+    if number < num_images {
+        links.push(format!(
+            r#"<a href="{}" rel="next">{}</a>"#,
+            escape_attribute(image_page_file(page, number + 1)),
+            text.next
+        ));
+    }
+    let photo_number = locale::fill(
+        text.photo_number,
+        &[
+            ("number", &number.to_string()),
+            ("total", &num_images.to_string()),
+        ],
+    );
+    let versions = page
+        .image_versions
+        .get(index)
+        .map_or(&[][..], |versions| versions);
+    let srcset = srcset(versions)
+        .map(|srcset| {
+            format!(
+                r#" srcset="{}" sizes="{IMAGE_SIZES}""#,
+                escape_attribute(srcset)
+            )
+        })
+        .unwrap_or_default();
+    let src = escape_attribute(&page.images[index]);
+    let mut picture = format!(
+        r#"<img src="{src}"{srcset} alt="{alt}">"#,
+        alt = escape_attribute(caption.as_deref().unwrap_or(&photo_number)),
+    );
+    if let Some(source_html) = alternative_source_html(versions) {
+        picture = format!("<picture>{source_html}{picture}</picture>");
+    }
+    let mut body = format!(
+        r#"<nav>{links}</nav>
+        <figure>
+          <a href="{src}">{picture}</a>
+          <figcaption>{caption}{photo_number}</figcaption>
+        </figure>"#,
+        links = links.join(" | "),
+        caption = caption
+            .as_deref()
+            .map(|c| format!("<i>{}</i>. ", escape_text(c)))
+            .unwrap_or_default(),
+    );
+    let summary = page
+        .image_metadata
+        .get(index)
+        .map(|metadata| metadata_summary(metadata, options))
+        .unwrap_or_default();
+    if !summary.is_empty() {
+        body.push_str("\n        <dl>\n");
+        for (label, value) in summary {
+            body.push_str(&format!(
+                "<dt>{}</dt><dd>{}</dd>\n",
+                escape_text(label),
+                escape_text(value)
+            ));
+        }
+        body.push_str("</dl>");
+    }
+    layout::render_page(
+        &format!("{} ({number}/{num_images})", page.title),
+        &body,
+        options,
+    )
+}
+
+/// Writes a page for each image in `page`.
+pub fn write_image_pages(
+    page: &Page,
+    output_dir: &std::path::Path,
+    options: &SiteOptions,
+) -> Result<(), std::io::Error> {
+    for index in 0..page.images.len() {
+        std::fs::write(
+            output_dir.join(image_page_file(page, index + 1)),
+            make_image_page_html(page, index, options),
+        )?;
+    }
+    Ok(())
+}
+
+/// Makes the photo viewer with its labels in the language of `text`.
+pub fn make_lightbox_html(text: &Catalog) -> String {
+    LIGHTBOX_HTML
+        .replace("{photo_viewer}", &escape_attribute(text.photo_viewer))
+        .replace("{close}", &escape_attribute(text.close))
+        .replace("{previous_photo}", &escape_attribute(text.previous_photo))
+        .replace("{next_photo}", &escape_attribute(text.next_photo))
+}
+
+/// Styles and script for the viewer: clicking a photo (or pressing Enter on its link) shows it
+/// full size over the page, with arrow keys stepping between photos and Escape closing it.
+const LIGHTBOX_HTML: &str = r#"<style>
+  #lightbox { position: fixed; inset: 0; background: rgba(0, 0, 0, 0.9); display: flex;
+    flex-direction: column; align-items: center; justify-content: center; z-index: 10; }
+  #lightbox[hidden] { display: none; }
+  #lightbox img { max-width: 95vw; max-height: 85vh; }
+  #lightbox p { color: #eee; }
+  #lightbox button { position: absolute; background: none; border: none; color: #eee;
+    font-size: 2.5em; cursor: pointer; }
+</style>
+<div id="lightbox" hidden role="dialog" aria-modal="true" aria-label="{photo_viewer}">
+  <button type="button" data-step="0" style="top: 0.2em; right: 0.5em" aria-label="{close}">&times;</button>
+  <button type="button" data-step="-1" style="left: 0.3em" aria-label="{previous_photo}">&lsaquo;</button>
+  <img alt="">
+  <p></p>
+  <button type="button" data-step="1" style="right: 0.3em" aria-label="{next_photo}">&rsaquo;</button>
+</div>
+<script>
+(function () {
+  const links = [...document.querySelectorAll('a[data-full]')];
+  if (links.length === 0) return;
+  const box = document.getElementById('lightbox');
+  const img = box.querySelector('img');
+  const caption = box.querySelector('p');
+  let current = -1;
+  function show(i) {
+    current = (i + links.length) % links.length;
+    img.src = links[current].dataset.full;
+    img.alt = links[current].dataset.caption;
+    caption.textContent = (links[current].dataset.caption ? links[current].dataset.caption + ' — ' : '') +
+      (current + 1) + ' / ' + links.length;
+    if (box.hidden) {
+      box.hidden = false;
+      box.querySelector('button').focus();
+    }
+  }
+  function close() {
+    box.hidden = true;
+    if (current >= 0) links[current].focus();
+  }
+  links.forEach((link, i) => link.addEventListener('click', (e) => { e.preventDefault(); show(i); }));
+  document.querySelectorAll('img[id^="img-"]').forEach((el) => {
+    el.style.cursor = 'zoom-in';
+    el.addEventListener('click', (e) => { e.preventDefault(); show(parseInt(el.id.slice(4), 10) - 1); });
+  });
+  box.addEventListener('click', (e) => {
+    const step = e.target.dataset.step;
+    if (step === undefined) { if (e.target === box) close(); return; }
+    if (step === '0') close(); else show(current + parseInt(step, 10));
+  });
+  document.addEventListener('keydown', (e) => {
+    if (box.hidden) return;
+    if (e.key === 'Escape') close();
+    else if (e.key === 'ArrowLeft') show(current - 1);
+    else if (e.key === 'ArrowRight') show(current + 1);
+  });
+})();
+</script>"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_captions_uses_following_italics() {
+        let html = r#"<img src="a/001.jpeg" id="img-1"><p><i>Ardea herodias</i></p><img src="https://x"><img src="a/002.jpeg" id="img-2"><p>no caption</p>"#;
+        assert_eq!(
+            find_captions(html, 2),
+            vec![Some(String::from("Ardea herodias")), None]
+        );
+    }
+
+    #[test]
+    fn image_page_file_is_next_to_post() {
+        let page = Page {
+            output_file: String::from("heron_1f.html"),
+            ..Default::default()
+        };
+        assert_eq!(image_page_file(&page, 3), "heron_1f_img_3.html");
+    }
+
+    #[test]
+    fn add_srcsets_lists_versions() {
+        let html = r#"<img src="a/001.jpeg" id="img-1"><img src="a/002.svg" id="img-2">"#;
+        let versions = vec![
+            vec![
+                SizedImage {
+                    width: 800,
+                    path: String::from("a/001_800w.jpeg"),
+                    alternative: Some(String::from("a/001_800w.avif")),
+                },
+                SizedImage {
+                    width: 2000,
+                    path: String::from("a/001.jpeg"),
+                    alternative: None,
+                },
+            ],
+            vec![],
+        ];
+        assert_eq!(
+            add_srcsets(html, &versions),
+            format!(
+                r#"<picture><source srcset="a/001_800w.avif 800w, a/001.jpeg 2000w" sizes="{IMAGE_SIZES}" type="image/avif"><img src="a/001.jpeg" id="img-1" srcset="a/001_800w.jpeg 800w, a/001.jpeg 2000w" sizes="{IMAGE_SIZES}"></picture><img src="a/002.svg" id="img-2">"#
+            )
+        );
+    }
+}
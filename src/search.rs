@@ -0,0 +1,203 @@
+// Client-side full-text search: an inverted index plus a static page which queries it.
+
+use crate::{get_text_from_html, layout, Page, SiteOptions};
+
+use htmlize::escape_attribute;
+use serde_derive::Serialize;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const SEARCH_FILE: &str = "search.html";
+/// The index is JavaScript rather than JSON so that search.html also works from file:// URLs,
+/// where browsers refuse to fetch() local files.
+pub const SEARCH_INDEX_FILE: &str = "search_index.js";
+
+/// Words shorter than this aren't indexed.
+const MIN_TERM_LEN: usize = 2;
+
+/// Shown in search results.
+#[derive(Serialize)]
+struct SearchDoc<'a> {
+    title: &'a str,
+    url: &'a str,
+    date: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thumbnail: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct SearchIndex<'a> {
+    docs: Vec<SearchDoc<'a>>,
+    /// Maps each term to the ascending indices within docs of the posts containing it.
+    terms: BTreeMap<String, Vec<usize>>,
+}
+
+/// Splits text into lowercase terms. Must stay in sync with `tokenize` in SEARCH_SCRIPT.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= MIN_TERM_LEN)
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+fn page_terms(page: &Page) -> BTreeSet<String> {
+    let mut terms: BTreeSet<String> = BTreeSet::new();
+    terms.extend(tokenize(&page.title));
+    terms.extend(tokenize(&get_text_from_html(&page.post_html)));
+    if let Some(author) = &page.author {
+        terms.extend(tokenize(author));
+    }
+    for text in &page.i_text {
+        terms.extend(tokenize(text));
+    }
+    terms
+}
+
+fn make_search_index<'a>(pages: &'a [Page], options: &SiteOptions) -> SearchIndex<'a> {
+    let mut index = SearchIndex {
+        docs: Vec::with_capacity(pages.len()),
+        terms: BTreeMap::new(),
+    };
+    for (i, page) in pages.iter().enumerate() {
+        index.docs.push(SearchDoc {
+            title: &page.title,
+            url: &page.output_file,
+            date: options.format_date(page.post_date),
+            thumbnail: page.thumbnails.first().map(|t| t.as_str()),
+        });
+        for term in page_terms(page) {
+            index.terms.entry(term).or_default().push(i);
+        }
+    }
+    index
+}
+
+/// Matches posts containing every query word, treating the last word as a prefix so that
+/// results update while typing.
+const SEARCH_SCRIPT: &str = r#"
+function tokenize(text) {
+  return text.toLowerCase().split(/[^\p{L}\p{N}]+/u).filter((w) => [...w].length >= 2);
+}
+function docsForTerm(term, isPrefix) {
+  if (!isPrefix) return SEARCH_INDEX.terms[term] || [];
+  const docs = new Set();
+  for (const [key, ids] of Object.entries(SEARCH_INDEX.terms)) {
+    if (key.startsWith(term)) ids.forEach((id) => docs.add(id));
+  }
+  return [...docs];
+}
+function search(query) {
+  const words = tokenize(query);
+  if (words.length === 0) return [];
+  let matches = null;
+  words.forEach((word, i) => {
+    const ids = new Set(docsForTerm(word, i === words.length - 1));
+    matches = matches === null ? ids : new Set([...matches].filter((id) => ids.has(id)));
+  });
+  return [...matches].sort((a, b) => a - b).map((id) => SEARCH_INDEX.docs[id]);
+}
+function render(results) {
+  const list = document.getElementById('results');
+  list.replaceChildren();
+  for (const doc of results) {
+    const item = document.createElement('li');
+    const link = document.createElement('a');
+    link.href = doc.url;
+    link.textContent = doc.title;
+    item.append(link, ' (' + doc.date + ')');
+    if (doc.thumbnail) {
+      const img = document.createElement('img');
+      img.src = doc.thumbnail;
+      img.alt = '';
+      item.append(document.createElement('br'), img);
+    }
+    list.append(item);
+  }
+  const count = document.getElementById('count');
+  const templates = count.dataset;
+  const template =
+    results.length === 0 ? templates.templateZero
+    : results.length === 1 ? templates.templateOne
+    : templates.template;
+  count.textContent = template.replace('{count}', results.length);
+}
+const input = document.getElementById('query');
+input.addEventListener('input', () => render(search(input.value)));
+const initial = new URLSearchParams(location.search).get('q');
+if (initial) {
+  input.value = initial;
+  render(search(initial));
+}
+"#;
+
+fn make_search_html(options: &SiteOptions) -> String {
+    let text = options.locale.catalog();
+    let body = format!(
+        r#"<form role="search" action="{search}">
+          <input id="query" name="q" type="search" aria-label="{label}" autofocus>
+        </form>
+        <p id="count" data-template="{count_template}"
+          data-template-one="{count_template_one}" data-template-zero="{count_template_zero}"></p>
+        <ul id="results"></ul>
+        <script src="{index}"></script>
+        <script>{script}</script>"#,
+        search = SEARCH_FILE,
+        label = escape_attribute(text.search),
+        count_template = escape_attribute(text.post_count),
+        count_template_one = escape_attribute(text.post_count_one),
+        count_template_zero = escape_attribute(text.post_count_template(0)),
+        index = SEARCH_INDEX_FILE,
+        script = SEARCH_SCRIPT,
+    );
+    layout::render_page(text.search, &body, options)
+}
+
+pub fn write_search_files(
+    pages: &[Page],
+    output_dir: &Path,
+    options: &SiteOptions,
+) -> Result<(), io::Error> {
+    let index_json = serde_json::to_string(&make_search_index(pages, options))?;
+    fs::write(
+        output_dir.join(SEARCH_INDEX_FILE),
+        format!("const SEARCH_INDEX = {index_json};\n"),
+    )?;
+    fs::write(output_dir.join(SEARCH_FILE), make_search_html(options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenize_splits_on_punctuation() {
+        assert_eq!(
+            tokenize("Héron, l'été à Lac-Blanc: 3 photos!"),
+            vec!["héron", "été", "lac", "blanc", "photos"]
+        );
+    }
+
+    #[test]
+    fn search_index_maps_terms_to_docs() {
+        let pages = vec![
+            Page {
+                title: String::from("Heron"),
+                post_html: String::from("<p>A <b>heron</b> at the lake</p>"),
+                ..Default::default()
+            },
+            Page {
+                title: String::from("Lake"),
+                author: Some(String::from("Jane")),
+                ..Default::default()
+            },
+        ];
+        let index = make_search_index(&pages, &Default::default());
+        assert_eq!(index.terms["heron"], vec![0]);
+        assert_eq!(index.terms["lake"], vec![0, 1]);
+        assert_eq!(index.terms["jane"], vec![1]);
+        assert!(!index.terms.contains_key("a"));
+    }
+}
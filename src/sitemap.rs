@@ -0,0 +1,102 @@
+// sitemap.xml and robots.txt for search engines.
+
+use crate::{Page, SiteOptions};
+
+use htmlize::escape_text;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const SITEMAP_FILE: &str = "sitemap.xml";
+pub const ROBOTS_FILE: &str = "robots.txt";
+
+/// Sitemaps must use absolute URLs, so this is only useful when base_url is set.
+fn make_sitemap(pages: &[Page], options: &SiteOptions) -> String {
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9"
+        xmlns:image="http://www.google.com/schemas/sitemap-image/1.1">
+"#,
+    );
+    for page in pages {
+        xml.push_str(&format!(
+            "  <url>\n    <loc>{loc}</loc>\n    <lastmod>{lastmod}</lastmod>\n",
+            loc = escape_text(options.url_for(&page.output_file)),
+            lastmod = page.post_date.format("%Y-%m-%d"),
+        ));
+        for image in &page.images {
+            xml.push_str(&format!(
+                "    <image:image><image:loc>{}</image:loc></image:image>\n",
+                escape_text(options.url_for(image))
+            ));
+        }
+        xml.push_str("  </url>\n");
+    }
+    xml.push_str("</urlset>\n");
+    xml
+}
+
+fn make_robots_txt(options: &SiteOptions) -> String {
+    if let Some(robots_txt) = &options.robots_txt {
+        return robots_txt.clone();
+    }
+    let mut robots_txt = String::from("User-agent: *\nAllow: /\n");
+    if options.base_url.is_some() {
+        robots_txt.push_str(&format!("Sitemap: {}\n", options.url_for(SITEMAP_FILE)));
+    }
+    robots_txt
+}
+
+pub fn write_sitemap(
+    pages: &[Page],
+    output_dir: &Path,
+    options: &SiteOptions,
+) -> Result<(), io::Error> {
+    fs::write(output_dir.join(SITEMAP_FILE), make_sitemap(pages, options))
+}
+
+pub fn write_robots_txt(output_dir: &Path, options: &SiteOptions) -> Result<(), io::Error> {
+    fs::write(output_dir.join(ROBOTS_FILE), make_robots_txt(options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::NaiveDate;
+
+    #[test]
+    fn sitemap_lists_pages_and_images() {
+        let options = SiteOptions {
+            base_url: Some(String::from("https://example.com")),
+            ..Default::default()
+        };
+        let pages = vec![Page {
+            output_file: String::from("heron_1.html"),
+            post_date: NaiveDate::from_ymd_opt(2023, 7, 19).unwrap(),
+            images: vec![String::from("heron_1_images/001.jpeg")],
+            ..Default::default()
+        }];
+        let sitemap = make_sitemap(&pages, &options);
+        assert!(sitemap.contains(
+            "  <url>
+    <loc>https://example.com/heron_1.html</loc>
+    <lastmod>2023-07-19</lastmod>
+    <image:image><image:loc>https://example.com/heron_1_images/001.jpeg</image:loc></image:image>
+  </url>"
+        ));
+    }
+
+    #[test]
+    fn robots_txt_points_at_sitemap() {
+        let options = SiteOptions {
+            base_url: Some(String::from("https://example.com/")),
+            ..Default::default()
+        };
+        assert_eq!(
+            make_robots_txt(&options),
+            "User-agent: *\nAllow: /\nSitemap: https://example.com/sitemap.xml\n"
+        );
+    }
+}
@@ -0,0 +1,300 @@
+// Removal of metadata from published images by rewriting JPEG segments and PNG chunks, without
+// touching the compressed pixel data.
+
+use exif::{In, Reader, Tag};
+
+/// What to remove from images.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum StripMetadata {
+    /// Everything except what's needed to display the image: orientation and color profile.
+    All,
+    /// GPS positions only, including any XMP, which can repeat them.
+    Gps,
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const EXIF_PREFIX: &[u8] = b"Exif\0\0";
+const GPS_IFD_TAG: u16 = 0x8825;
+
+fn read_u16(tiff: &[u8], pos: usize, big_endian: bool) -> Option<u16> {
+    let bytes: [u8; 2] = tiff.get(pos..pos.checked_add(2)?)?.try_into().ok()?;
+    Some(if big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    })
+}
+
+fn read_u32(tiff: &[u8], pos: usize, big_endian: bool) -> Option<u32> {
+    let bytes: [u8; 4] = tiff.get(pos..pos.checked_add(4)?)?.try_into().ok()?;
+    Some(if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    })
+}
+
+/// Size in bytes of one value of a TIFF field type.
+fn tiff_type_size(field_type: u16) -> Option<usize> {
+    match field_type {
+        1 | 2 | 6 | 7 => Some(1),
+        3 | 8 => Some(2),
+        4 | 9 | 11 => Some(4),
+        5 | 10 | 12 => Some(8),
+        _ => None,
+    }
+}
+
+/// Empties the GPS IFD of TIFF-structured EXIF data in place, zeroing its entries and values
+/// so that every other offset stays valid. Returns None if the data is malformed.
+fn remove_gps(tiff: &mut [u8]) -> Option<()> {
+    let big_endian = match tiff.get(0..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let ifd0 = read_u32(tiff, 4, big_endian)? as usize;
+    let count = read_u16(tiff, ifd0, big_endian)? as usize;
+    for i in 0..count {
+        let entry = ifd0 + 2 + 12 * i;
+        if read_u16(tiff, entry, big_endian)? != GPS_IFD_TAG {
+            continue;
+        }
+        let gps_ifd = read_u32(tiff, entry + 8, big_endian)? as usize;
+        let gps_count = read_u16(tiff, gps_ifd, big_endian)? as usize;
+        let gps_end = gps_ifd + 2 + 12 * gps_count + 4;
+        if gps_end > tiff.len() {
+            return None;
+        }
+        for j in 0..gps_count {
+            let gps_entry = gps_ifd + 2 + 12 * j;
+            let size = tiff_type_size(read_u16(tiff, gps_entry + 2, big_endian)?)?
+                .checked_mul(read_u32(tiff, gps_entry + 4, big_endian)? as usize)?;
+            // Values of up to 4 bytes are stored within the entry itself.
+            if size > 4 {
+                let offset = read_u32(tiff, gps_entry + 8, big_endian)? as usize;
+                tiff.get_mut(offset..offset.checked_add(size)?)?.fill(0);
+            }
+        }
+        // Leaves an IFD with no entries and no next IFD.
+        tiff[gps_ifd..gps_end].fill(0);
+        return Some(());
+    }
+    Some(())
+}
+
+/// EXIF orientation of TIFF-structured EXIF data, if it's anything but the default.
+fn orientation(tiff: &[u8]) -> Option<u16> {
+    let exif = Reader::new().read_raw(tiff.to_vec()).ok()?;
+    let value = exif
+        .get_field(Tag::Orientation, In::PRIMARY)?
+        .value
+        .get_uint(0)?;
+    (2..=8).contains(&value).then_some(value as u16)
+}
+
+/// TIFF-structured EXIF data holding only an orientation.
+fn orientation_only_tiff(orientation: u16) -> Vec<u8> {
+    let mut tiff = b"MM\0\x2A\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01".to_vec();
+    tiff.extend(orientation.to_be_bytes());
+    tiff.extend([0; 6]);
+    tiff
+}
+
+/// Returns the replacement for TIFF-structured EXIF data, or None to drop it.
+fn strip_tiff(tiff: &[u8], mode: StripMetadata) -> Result<Option<Vec<u8>>, ()> {
+    match mode {
+        StripMetadata::All => Ok(orientation(tiff).map(orientation_only_tiff)),
+        StripMetadata::Gps => {
+            let mut tiff = tiff.to_vec();
+            remove_gps(&mut tiff).ok_or(())?;
+            Ok(Some(tiff))
+        }
+    }
+}
+
+fn strip_jpeg(contents: &[u8], mode: StripMetadata) -> Option<Vec<u8>> {
+    if !contents.starts_with(b"\xFF\xD8") {
+        return None;
+    }
+    let mut output = contents[..2].to_vec();
+    let mut pos = 2;
+    loop {
+        if *contents.get(pos)? != 0xFF {
+            return None;
+        }
+        let marker = *contents.get(pos + 1)?;
+        match marker {
+            // Fill byte.
+            0xFF => {
+                pos += 1;
+                continue;
+            }
+            // Start of scan or end of image: the rest is pixel data.
+            0xDA | 0xD9 => {
+                output.extend(&contents[pos..]);
+                return Some(output);
+            }
+            // Markers without a length.
+            0x01 | 0xD0..=0xD7 => {
+                output.extend(&contents[pos..pos + 2]);
+                pos += 2;
+                continue;
+            }
+            _ => {}
+        }
+        let length = read_u16(contents, pos + 2, true)? as usize;
+        let end = pos + 2 + length;
+        if length < 2 || end > contents.len() {
+            return None;
+        }
+        let data = &contents[pos + 4..end];
+        let is_exif = marker == 0xE1 && data.starts_with(EXIF_PREFIX);
+        let replacement: Option<Vec<u8>> = if is_exif {
+            strip_tiff(&data[EXIF_PREFIX.len()..], mode)
+                .ok()?
+                .map(|tiff| [EXIF_PREFIX, &tiff].concat())
+        } else if marker == 0xE1 {
+            // XMP, which can hold anything EXIF can.
+            None
+        } else if mode == StripMetadata::Gps {
+            Some(data.to_vec())
+        } else {
+            let is_kept = match marker {
+                0xE0 => true,
+                0xE2 => data.starts_with(b"ICC_PROFILE\0"),
+                0xEE => data.starts_with(b"Adobe"),
+                // Other application segments and comments.
+                0xE1..=0xEF | 0xFE => false,
+                _ => true,
+            };
+            is_kept.then(|| data.to_vec())
+        };
+        if let Some(data) = replacement {
+            output.extend([0xFF, marker]);
+            output.extend(u16::try_from(data.len() + 2).ok()?.to_be_bytes());
+            output.extend(data);
+        }
+        pos = end;
+    }
+}
+
+fn push_png_chunk(output: &mut Vec<u8>, chunk_type: &[u8], data: &[u8]) {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(chunk_type);
+    hasher.update(data);
+    output.extend((data.len() as u32).to_be_bytes());
+    output.extend(chunk_type);
+    output.extend(data);
+    output.extend(hasher.finalize().to_be_bytes());
+}
+
+fn strip_png(contents: &[u8], mode: StripMetadata) -> Option<Vec<u8>> {
+    if !contents.starts_with(PNG_SIGNATURE) {
+        return None;
+    }
+    let mut output = PNG_SIGNATURE.to_vec();
+    let mut pos = PNG_SIGNATURE.len();
+    while pos < contents.len() {
+        let length = read_u32(contents, pos, true)? as usize;
+        let chunk_type = contents.get(pos + 4..pos + 8)?;
+        let data = contents.get(pos + 8..(pos + 8).checked_add(length)?)?;
+        let end = pos + 12 + length;
+        if end > contents.len() {
+            return None;
+        }
+        match (chunk_type, mode) {
+            (b"eXIf", _) => {
+                if let Some(tiff) = strip_tiff(data, mode).ok()? {
+                    push_png_chunk(&mut output, chunk_type, &tiff);
+                }
+            }
+            (b"tEXt" | b"zTXt" | b"iTXt" | b"tIME", StripMetadata::All) => {}
+            (b"iTXt", StripMetadata::Gps) if data.starts_with(b"XML:com.adobe.xmp\0") => {}
+            _ => output.extend(&contents[pos..end]),
+        }
+        pos = end;
+    }
+    Some(output)
+}
+
+/// Returns the image with metadata removed, or None if it isn't a JPEG or PNG or is malformed.
+pub fn strip(contents: &[u8], content_type: &str, mode: StripMetadata) -> Option<Vec<u8>> {
+    match content_type {
+        "image/jpeg" => strip_jpeg(contents, mode),
+        "image/png" => strip_png(contents, mode),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_metadata;
+    use exif::experimental::Writer;
+    use exif::{Field, Rational, Value};
+    use image::DynamicImage;
+    use std::io::Cursor;
+
+    /// A JPEG with EXIF holding an orientation, a camera model and a GPS latitude.
+    fn jpeg_with_gps() -> Vec<u8> {
+        let mut contents: Vec<u8> = Vec::new();
+        DynamicImage::new_rgb8(8, 8)
+            .write_to(&mut Cursor::new(&mut contents), image::ImageFormat::Jpeg)
+            .unwrap();
+        let degrees = Value::Rational(vec![Rational { num: 45, denom: 1 }; 3]);
+        let fields = [
+            (Tag::Orientation, Value::Short(vec![6])),
+            (Tag::Model, Value::Ascii(vec![b"Pixel 7".to_vec()])),
+            (Tag::GPSLatitudeRef, Value::Ascii(vec![b"N".to_vec()])),
+            (Tag::GPSLatitude, degrees.clone()),
+            (Tag::GPSLongitudeRef, Value::Ascii(vec![b"E".to_vec()])),
+            (Tag::GPSLongitude, degrees),
+        ]
+        .map(|(tag, value)| Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value,
+        });
+        let mut writer = Writer::new();
+        for field in &fields {
+            writer.push_field(field);
+        }
+        let mut tiff = Cursor::new(Vec::new());
+        writer.write(&mut tiff, true).unwrap();
+        let data = [EXIF_PREFIX, tiff.get_ref()].concat();
+        let mut segment = vec![0xFF, 0xE1];
+        segment.extend(((data.len() + 2) as u16).to_be_bytes());
+        segment.extend(data);
+        contents.splice(2..2, segment);
+        contents
+    }
+
+    fn scan_data(jpeg: &[u8]) -> &[u8] {
+        let start = jpeg.windows(2).position(|w| w == b"\xFF\xDA").unwrap();
+        &jpeg[start..]
+    }
+
+    #[test]
+    fn strip_jpeg_gps_keeps_other_exif() {
+        let original = jpeg_with_gps();
+        assert!(image_metadata::read(&original, true).gps.is_some());
+        let stripped = strip(&original, "image/jpeg", StripMetadata::Gps).unwrap();
+        let metadata = image_metadata::read(&stripped, true);
+        assert_eq!(metadata.gps, None);
+        assert_eq!(metadata.camera_model.as_deref(), Some("Pixel 7"));
+        assert_eq!(stripped.len(), original.len());
+        assert_eq!(scan_data(&stripped), scan_data(&original));
+    }
+
+    #[test]
+    fn strip_jpeg_all_keeps_orientation() {
+        let original = jpeg_with_gps();
+        let stripped = strip(&original, "image/jpeg", StripMetadata::All).unwrap();
+        assert_eq!(image_metadata::read(&stripped, true), Default::default());
+        let tiff = &stripped[stripped.windows(6).position(|w| w == EXIF_PREFIX).unwrap() + 6..];
+        assert_eq!(orientation(tiff), Some(6));
+        assert_eq!(scan_data(&stripped), scan_data(&original));
+        assert!(image::load_from_memory(&stripped).is_ok());
+    }
+}
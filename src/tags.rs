@@ -0,0 +1,203 @@
+// Tag pages built from the italic captions (i_text) within posts.
+
+use crate::layout;
+use crate::{normalize_key, Page, SiteOptions};
+
+use htmlize::{escape_attribute, escape_text};
+use serde_derive::Deserialize;
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const TAGS_INDEX_FILE: &str = "tags.html";
+
+/// Smallest and largest font sizes, as percentages, used in the tag cloud.
+const MIN_CLOUD_SIZE: usize = 100;
+const MAX_CLOUD_SIZE: usize = 250;
+
+/// Format of the file passed via --tag_config.
+#[derive(Default, Deserialize)]
+struct TagConfigFile {
+    /// Maps each preferred tag name to other phrases that should be merged into it.
+    #[serde(default)]
+    synonyms: HashMap<String, Vec<String>>,
+    /// Phrases which should never become tags.
+    #[serde(default)]
+    stopwords: Vec<String>,
+}
+
+/// Rules for cleaning up tags, keyed by normalized phrase.
+#[derive(Default)]
+pub struct TagConfig {
+    /// Maps normalized synonyms to the preferred tag name.
+    synonyms: HashMap<String, String>,
+    stopwords: HashSet<String>,
+}
+
+/// Posts sharing a single tag.
+pub struct Tag<'a> {
+    pub name: String,
+    /// Name within output dir.
+    pub output_file: String,
+    /// Posts in the same order as the site.
+    pub pages: Vec<&'a Page>,
+}
+
+/// Loads a JSON file such as
+/// `{"synonyms": {"Great Blue Heron": ["Ardea herodias"]}, "stopwords": ["sic"]}`.
+pub fn load_config(path: &Path) -> Result<TagConfig, io::Error> {
+    let file: TagConfigFile = serde_json::from_slice(&fs::read(path)?)?;
+    let mut config: TagConfig = Default::default();
+    for (preferred, others) in file.synonyms {
+        for other in others {
+            config
+                .synonyms
+                .insert(normalize_key(&other), preferred.clone());
+        }
+        config.synonyms.insert(normalize_key(&preferred), preferred);
+    }
+    config.stopwords = file.stopwords.iter().map(|s| normalize_key(s)).collect();
+    Ok(config)
+}
+
+/// Returns the key and display name of the tag for an i_text phrase, if it should be a tag.
+pub fn tag_for_text(text: &str, config: &TagConfig) -> Option<(String, String)> {
+    let key = normalize_key(text);
+    if key.is_empty() || config.stopwords.contains(&key) {
+        return None;
+    }
+    match config.synonyms.get(&key) {
+        Some(preferred) => Some((normalize_key(preferred), preferred.clone())),
+        None => Some((
+            key,
+            text.split_whitespace().collect::<Vec<&str>>().join(" "),
+        )),
+    }
+}
+
+/// Groups pages by tag, sorted by tag name. A tag is displayed using the spelling from its
+/// first post unless a synonym provides a preferred name.
+pub fn group_by_tag<'a>(pages: &'a [Page], config: &TagConfig) -> Vec<Tag<'a>> {
+    let mut by_key: HashMap<String, Tag> = HashMap::new();
+    for page in pages {
+        let mut seen: HashSet<String> = HashSet::new();
+        for text in &page.i_text {
+            let Some((key, name)) = tag_for_text(text, config) else {
+                continue;
+            };
+            if !seen.insert(key.clone()) {
+                continue;
+            }
+            by_key
+                .entry(key.clone())
+                .or_insert_with(|| Tag {
+                    output_file: format!("tag_{}.html", crate::make_basename(&key, &key)),
+                    name,
+                    pages: vec![],
+                })
+                .pages
+                .push(page);
+        }
+    }
+    let mut tags: Vec<Tag> = by_key.into_values().collect();
+    tags.sort_by_cached_key(|tag| normalize_key(&tag.name));
+    tags
+}
+
+fn cloud_size(count: usize, max_count: usize) -> usize {
+    if max_count <= 1 {
+        return MIN_CLOUD_SIZE;
+    }
+    MIN_CLOUD_SIZE + (MAX_CLOUD_SIZE - MIN_CLOUD_SIZE) * (count - 1) / (max_count - 1)
+}
+
+fn make_tags_index_html(tags: &[Tag], options: &SiteOptions) -> String {
+    let text = options.locale.catalog();
+    let max_count = tags.iter().map(|tag| tag.pages.len()).max().unwrap_or(0);
+    let mut body = String::from("<p class=\"tag-cloud\">\n");
+    for tag in tags {
+        body.push_str(&format!(
+            "<a href=\"{href}\" style=\"font-size: {size}%\" title=\"{count}\">{name}</a>\n",
+            href = escape_attribute(&tag.output_file),
+            size = cloud_size(tag.pages.len(), max_count),
+            count = text.format_post_count(tag.pages.len()),
+            name = escape_text(&tag.name),
+        ));
+    }
+    body.push_str("</p>");
+    layout::render_page(text.tags, &body, options)
+}
+
+fn make_tag_html(tag: &Tag, options: &SiteOptions) -> String {
+    let body = format!(
+        r#"<nav><a href="{index}">{all_tags}</a></nav>
+        {posts}"#,
+        index = TAGS_INDEX_FILE,
+        all_tags = options.locale.catalog().all_tags,
+        posts = layout::render_post_list(&tag.pages, options),
+    );
+    layout::render_page(&tag.name, &body, options)
+}
+
+/// Writes a page per tag plus the tag cloud index, returning the number of tags.
+pub fn write_tag_pages(
+    pages: &[Page],
+    output_dir: &Path,
+    options: &SiteOptions,
+) -> Result<usize, io::Error> {
+    let tags = group_by_tag(pages, &options.tag_config);
+    for tag in &tags {
+        fs::write(
+            output_dir.join(&tag.output_file),
+            make_tag_html(tag, options),
+        )?;
+    }
+    fs::write(
+        output_dir.join(TAGS_INDEX_FILE),
+        make_tags_index_html(&tags, options),
+    )?;
+    Ok(tags.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page_with_tags(title: &str, i_text: &[&str]) -> Page {
+        Page {
+            title: String::from(title),
+            i_text: i_text.iter().map(|s| String::from(*s)).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn group_by_tag_merges_case_and_synonyms() {
+        let config = TagConfig {
+            synonyms: HashMap::from([(
+                String::from("ardea herodias"),
+                String::from("Great Blue Heron"),
+            )]),
+            stopwords: HashSet::from([String::from("sic")]),
+        };
+        let pages = vec![
+            page_with_tags("a", &["Lac Blanc", "Ardea herodias", "sic"]),
+            page_with_tags("b", &["lac\u{a0}blanc", "Great blue heron"]),
+        ];
+        let tags = group_by_tag(&pages, &config);
+        let summary: Vec<(&str, usize)> = tags
+            .iter()
+            .map(|tag| (tag.name.as_str(), tag.pages.len()))
+            .collect();
+        assert_eq!(summary, vec![("Great Blue Heron", 2), ("Lac Blanc", 2)]);
+    }
+
+    #[test]
+    fn cloud_size_range() {
+        assert_eq!(cloud_size(1, 1), MIN_CLOUD_SIZE);
+        assert_eq!(cloud_size(1, 5), MIN_CLOUD_SIZE);
+        assert_eq!(cloud_size(5, 5), MAX_CLOUD_SIZE);
+    }
+}
@@ -1,22 +1,357 @@
-use image;
-use image::imageops;
-
-use std::io::Cursor;
-
-const THUMBNAIL_HEIGHT: u32 = 150;
-
-pub fn create_thumbnail(contents: &[u8], thumbnail_path: &std::path::PathBuf) {
-    let reader = image::ImageReader::new(Cursor::new(contents))
-        .with_guessed_format()
-        .unwrap();
-    let image = reader.decode().unwrap();
-    let original_height = image.height();
-    let original_width = image.width();
-    let width =
-        ((original_width as f32) / (original_height as f32) * THUMBNAIL_HEIGHT as f32) as u32;
-    let thumbnail = imageops::thumbnail(&image, width, THUMBNAIL_HEIGHT);
-    image::DynamicImage::ImageRgba8(thumbnail)
-        .into_rgb8()
-        .save(thumbnail_path)
-        .expect("Failed to save");
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::{imageops, DynamicImage, ImageDecoder};
+use serde_derive::Serialize;
+
+use std::fs;
+use std::io::{self, BufWriter, Cursor};
+use std::path::Path;
+
+/// Format for copies of images, besides the JPEG or PNG fallbacks which are always written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Jpeg,
+    /// Lossless, so only used for images with transparency, where the fallback is a PNG rather
+    /// than a smaller JPEG.
+    Webp,
+    Avif,
+}
+
+/// Sizes and format of the copies made of each image which can be decoded.
+#[derive(Clone, Debug)]
+pub struct DerivativeOptions {
+    pub thumbnail_height: u32,
+    /// Widths of resized copies for browsers to choose from, such as 800 and 1600. Copies are
+    /// only made at widths narrower than the image.
+    pub widths: Vec<u32>,
+    pub format: OutputFormat,
+    /// Quality from 1 to 100 for JPEG and AVIF copies.
+    pub quality: u8,
+}
+
+impl Default for DerivativeOptions {
+    fn default() -> Self {
+        DerivativeOptions {
+            thumbnail_height: 150,
+            widths: Vec::new(),
+            format: OutputFormat::Jpeg,
+            quality: 75,
+        }
+    }
+}
+
+/// A version of an image at a particular width.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SizedImage {
+    pub width: u32,
+    /// Path within output dir.
+    pub path: String,
+    /// Path within output dir of the same version in a format not every browser supports.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alternative: Option<String>,
+}
+
+/// Names within an images dir of a copy of an image, which every browser can show, and of the
+/// same copy in the output format if that's WebP or AVIF.
+#[derive(Debug, PartialEq)]
+pub struct DerivativeFiles {
+    pub file: String,
+    pub alternative: Option<String>,
+}
+
+/// The copies made of an image.
+pub struct Derivatives {
+    pub thumbnail: DerivativeFiles,
+    /// Width of the image itself, after applying its orientation.
+    pub width: u32,
+    /// Resized copies by width, narrowest first.
+    pub resized: Vec<(u32, DerivativeFiles)>,
+}
+
+/// Name within output dir of the thumbnail shown for images which couldn't be decoded.
+pub const PLACEHOLDER_FILE: &str = "placeholder_thumbnail.svg";
+
+const PLACEHOLDER_SVG: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" width="150" height="150" viewBox="0 0 150 150">
+  <rect width="150" height="150" fill="#d6d6d3"/>
+  <path d="M40 105 L65 70 L82 92 L95 78 L115 105 Z" fill="#a1a1a6"/>
+  <circle cx="95" cy="55" r="9" fill="#a1a1a6"/>
+</svg>
+"##;
+
+pub fn write_placeholder(output_dir: &Path) -> Result<(), io::Error> {
+    fs::write(output_dir.join(PLACEHOLDER_FILE), PLACEHOLDER_SVG)
+}
+
+/// Whether images with the given MIME type can be decoded to make thumbnails.
+pub fn can_decode(content_type: &str) -> bool {
+    image::ImageFormat::from_mime_type(content_type).is_some_and(|format| format.reading_enabled())
+}
+
+/// Decodes an image, rotating and flipping it as its EXIF orientation says browsers would.
+pub fn decode_oriented(contents: &[u8]) -> image::ImageResult<DynamicImage> {
+    let mut decoder = image::ImageReader::new(Cursor::new(contents))
+        .with_guessed_format()?
+        .into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+/// Whether any pixel of `image` is at all transparent.
+fn has_transparency(image: &DynamicImage) -> bool {
+    image.color().has_alpha() && image.to_rgba8().pixels().any(|pixel| pixel[3] < u8::MAX)
+}
+
+/// Saves `image` as a JPEG, or as a PNG if it's `transparent`, since JPEG can't hold that.
+fn save_fallback(
+    image: &DynamicImage,
+    transparent: bool,
+    quality: u8,
+    dir: &Path,
+    name: &str,
+) -> image::ImageResult<String> {
+    if transparent {
+        let file = format!("{name}.png");
+        image.save(dir.join(&file))?;
+        return Ok(file);
+    }
+    let file = format!("{name}.jpeg");
+    let writer = BufWriter::new(fs::File::create(dir.join(&file))?);
+    image
+        .to_rgb8()
+        .write_with_encoder(JpegEncoder::new_with_quality(writer, quality))?;
+    Ok(file)
+}
+
+/// Saves `image` at full size as `name` plus an extension, as a JPEG or a PNG if it has
+/// transparency, leaving out any metadata the image was decoded from.
+pub fn save_copy(
+    image: &DynamicImage,
+    quality: u8,
+    dir: &Path,
+    name: &str,
+) -> image::ImageResult<String> {
+    save_fallback(image, has_transparency(image), quality, dir, name)
+}
+
+/// Saves `image` in the output format if that's AVIF, or WebP and the image is `transparent`.
+fn save_alternative(
+    image: &DynamicImage,
+    transparent: bool,
+    options: &DerivativeOptions,
+    dir: &Path,
+    name: &str,
+) -> image::ImageResult<Option<String>> {
+    let file = match options.format {
+        OutputFormat::Jpeg => return Ok(None),
+        OutputFormat::Webp if !transparent => return Ok(None),
+        OutputFormat::Webp => format!("{name}.webp"),
+        OutputFormat::Avif => format!("{name}.avif"),
+    };
+    let writer = BufWriter::new(fs::File::create(dir.join(&file))?);
+    let image = DynamicImage::ImageRgba8(image.to_rgba8());
+    match options.format {
+        OutputFormat::Jpeg => unreachable!(),
+        OutputFormat::Webp => image.write_with_encoder(WebPEncoder::new_lossless(writer))?,
+        // A faster speed than the encoder's default, since sites can have thousands of images.
+        OutputFormat::Avif => image.write_with_encoder(AvifEncoder::new_with_speed_quality(
+            writer,
+            6,
+            options.quality,
+        ))?,
+    }
+    Ok(Some(file))
+}
+
+/// Scales `image` to fit `width` and `height`, then saves it as `name` plus an extension for
+/// each format written.
+fn save_resized(
+    image: &DynamicImage,
+    transparent: bool,
+    width: u32,
+    height: u32,
+    options: &DerivativeOptions,
+    dir: &Path,
+    name: &str,
+) -> image::ImageResult<DerivativeFiles> {
+    let resized = DynamicImage::ImageRgba8(imageops::thumbnail(image, width.max(1), height.max(1)));
+    Ok(DerivativeFiles {
+        file: save_fallback(&resized, transparent, options.quality, dir, name)?,
+        alternative: save_alternative(&resized, transparent, options, dir, name)?,
+    })
+}
+
+/// Width of `image` scaled to `height`, keeping its aspect ratio.
+fn width_at_height(image: &DynamicImage, height: u32) -> u32 {
+    ((image.width() as f32) / (image.height() as f32) * height as f32) as u32
+}
+
+/// Writes a thumbnail `{stem}_thumbnail` of a decoded image into `dir`, along with a copy
+/// `{stem}_{width}w` at each of the widths in `options` narrower than the image. Each is a
+/// JPEG, or a PNG if the image has transparency, plus a copy in the output format if that's
+/// AVIF, or WebP and the image has transparency.
+pub fn create_derivatives(
+    image: &DynamicImage,
+    dir: &Path,
+    stem: &str,
+    options: &DerivativeOptions,
+) -> image::ImageResult<Derivatives> {
+    // Decided from the image itself, since resizing can leave edges slightly transparent.
+    let transparent = has_transparency(image);
+    let thumbnail = save_resized(
+        image,
+        transparent,
+        width_at_height(image, options.thumbnail_height),
+        options.thumbnail_height,
+        options,
+        dir,
+        &format!("{stem}_thumbnail"),
+    )?;
+    let mut widths: Vec<u32> = options
+        .widths
+        .iter()
+        .copied()
+        .filter(|width| *width < image.width())
+        .collect();
+    widths.sort_unstable();
+    widths.dedup();
+    let mut resized: Vec<(u32, DerivativeFiles)> = Vec::new();
+    for width in widths {
+        let height =
+            ((image.height() as f32) / (image.width() as f32) * width as f32).round() as u32;
+        let files = save_resized(
+            image,
+            transparent,
+            width,
+            height,
+            options,
+            dir,
+            &format!("{stem}_{width}w"),
+        )?;
+        resized.push((width, files));
+    }
+    Ok(Derivatives {
+        thumbnail,
+        width: image.width(),
+        resized,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn decode_oriented_reports_corrupt_image() {
+        assert!(decode_oriented(b"\xFF\xD8\xFF\xE0 not really a JPEG").is_err());
+    }
+
+    #[test]
+    fn create_thumbnail_applies_orientation() {
+        let mut contents: Vec<u8> = Vec::new();
+        DynamicImage::new_rgb8(40, 20)
+            .write_to(&mut Cursor::new(&mut contents), image::ImageFormat::Jpeg)
+            .unwrap();
+        // An APP1 segment with a big-endian TIFF IFD holding Orientation = 6 (rotate 90° CW).
+        let exif: &[u8] = b"\xFF\xE1\x00\x22Exif\x00\x00MM\x00\x2A\x00\x00\x00\x08\x00\x01\x01\x12\x00\x03\x00\x00\x00\x01\x00\x06\x00\x00\x00\x00\x00\x00";
+        contents.splice(2..2, exif.iter().copied());
+        let test_dir = TestDir::new("create_thumbnail_applies_orientation");
+        let dir = test_dir.path();
+        let image = decode_oriented(&contents).unwrap();
+        let derivatives = create_derivatives(&image, dir, "001", &Default::default()).unwrap();
+        assert_eq!(
+            image::image_dimensions(dir.join(derivatives.thumbnail.file)).unwrap(),
+            (75, 150)
+        );
+    }
+
+    #[test]
+    fn create_derivatives_only_shrinks() {
+        let mut contents: Vec<u8> = Vec::new();
+        DynamicImage::new_rgb8(1000, 500)
+            .write_to(&mut Cursor::new(&mut contents), image::ImageFormat::Png)
+            .unwrap();
+        let test_dir = TestDir::new("create_derivatives_only_shrinks");
+        let dir = test_dir.path();
+        let options = DerivativeOptions {
+            thumbnail_height: 100,
+            widths: vec![1600, 400, 800],
+            ..Default::default()
+        };
+        let image = decode_oriented(&contents).unwrap();
+        let derivatives = create_derivatives(&image, dir, "001", &options).unwrap();
+        assert_eq!(derivatives.width, 1000);
+        let widths: Vec<u32> = derivatives
+            .resized
+            .iter()
+            .map(|(width, _)| *width)
+            .collect();
+        assert_eq!(widths, vec![400, 800]);
+        assert_eq!(derivatives.resized[1].1.file, "001_800w.jpeg");
+        assert_eq!(
+            image::image_dimensions(dir.join("001_800w.jpeg")).unwrap(),
+            (800, 400)
+        );
+        assert_eq!(
+            image::image_dimensions(dir.join(derivatives.thumbnail.file)).unwrap(),
+            (200, 100)
+        );
+    }
+
+    #[test]
+    fn create_derivatives_keeps_transparency() {
+        let mut contents: Vec<u8> = Vec::new();
+        DynamicImage::new_rgba8(300, 150)
+            .write_to(&mut Cursor::new(&mut contents), image::ImageFormat::Png)
+            .unwrap();
+        let test_dir = TestDir::new("create_derivatives_keeps_transparency");
+        let dir = test_dir.path();
+        let options = DerivativeOptions {
+            format: OutputFormat::Webp,
+            ..Default::default()
+        };
+        let image = decode_oriented(&contents).unwrap();
+        let thumbnail = create_derivatives(&image, dir, "001", &options)
+            .unwrap()
+            .thumbnail;
+        assert_eq!(
+            thumbnail,
+            DerivativeFiles {
+                file: String::from("001_thumbnail.png"),
+                alternative: Some(String::from("001_thumbnail.webp")),
+            }
+        );
+        let webp = image::open(dir.join("001_thumbnail.webp")).unwrap();
+        assert_eq!(webp.to_rgba8().get_pixel(0, 0)[3], 0);
+        let size = |file: &str| fs::metadata(dir.join(file)).unwrap().len();
+        assert!(size("001_thumbnail.webp") < size("001_thumbnail.png"));
+    }
+
+    #[test]
+    fn create_derivatives_leaves_out_webp_of_photos() {
+        let image = DynamicImage::ImageRgb8(image::RgbImage::from_fn(300, 150, |x, y| {
+            image::Rgb([(x % 256) as u8, (y * 3 % 256) as u8, ((x * y) % 256) as u8])
+        }));
+        let test_dir = TestDir::new("create_derivatives_leaves_out_webp_of_photos");
+        let dir = test_dir.path();
+        let options = DerivativeOptions {
+            format: OutputFormat::Webp,
+            ..Default::default()
+        };
+        let thumbnail = create_derivatives(&image, dir, "001", &options)
+            .unwrap()
+            .thumbnail;
+        assert_eq!(
+            thumbnail,
+            DerivativeFiles {
+                file: String::from("001_thumbnail.jpeg"),
+                alternative: None,
+            }
+        );
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 1);
+    }
 }